@@ -1,5 +1,6 @@
 use bytes::BytesMut;
 use futures::{lock::Mutex, AsyncRead, AsyncWrite};
+use postgres_protocol::message::backend;
 
 mod authentication;
 pub use authentication::*;
@@ -21,6 +22,32 @@ impl<'frontend> FrontendReceiver<'frontend> for Connection<'frontend> {
     }
 }
 
+/// A notification, sent with `NOTIFY` on a channel the connection is listening on.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub process_id: i32,
+    pub channel: String,
+    pub payload: String,
+}
+
+impl<'frontend> Connection<'frontend> {
+    /// Waits for the next notification on this connection. Should only be used on connections that
+    ///  do not run queries, as any other messages received in the meantime are dropped.
+    pub async fn next_notification(&self) -> Result<Notification, AnyError> {
+        let mut guard = self.conn.lock().await;
+
+        loop {
+            if let backend::Message::NotificationResponse(body) = guard.read_message().await? {
+                return Ok(Notification {
+                    process_id: body.process_id(),
+                    channel: body.channel()?.to_owned(),
+                    payload: body.message()?.to_owned(),
+                });
+            }
+        }
+    }
+}
+
 pub async fn connect<'a, T: 'a + Send + Sync + AsyncRead + AsyncWrite + Unpin>(
     stream: T,
     database: String,
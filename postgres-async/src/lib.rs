@@ -9,7 +9,7 @@ mod statement;
 pub mod types;
 
 pub use bindings::{BoundQuery, BoundStatement};
-pub use connect::{connect, Authentication, Connection};
+pub use connect::{connect, Authentication, Connection, Notification};
 pub use frontend::{Frontend, FrontendReceiver};
 pub use statement::Statement;
 
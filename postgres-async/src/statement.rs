@@ -44,6 +44,30 @@ impl<'frontend> Statement<'frontend> {
         }
     }
 
+    /// Closes the statement on the server, freeing the resources held by it.
+    pub async fn close(self, conn: &impl FrontendReceiver<'frontend>) -> Result<(), AnyError> {
+        let mut guard = conn.connection().lock().await;
+
+        let mut buf = Vec::new();
+        frontend::close(b'S', &self.name, &mut buf)?;
+        buf.extend_from_slice(b"H\x00\x00\x00\x04");
+
+        guard.write_data(&buf).await?;
+        loop {
+            let msg = guard.read_message().await?;
+
+            match msg {
+                backend::Message::CloseComplete => return Ok(()),
+
+                backend::Message::ErrorResponse(err) => {
+                    return Err(make_err(err.fields()).into());
+                }
+
+                _ => return Err("should not occur here".into()),
+            }
+        }
+    }
+
     pub async fn bind<'stmt>(
         &'stmt self,
         conn: &impl FrontendReceiver<'frontend>,
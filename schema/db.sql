@@ -18,21 +18,136 @@ CREATE TABLE quad (
     language text null
 );
 
+-- the version of every entity, incremented on every write. entities without a row are at 0.
+-- existing databases are upgraded with upgrade/002_entity_version.sql
+CREATE TABLE entity_version (
+    quad_id integer PRIMARY KEY references attribute,
+    version bigint not null
+);
+
 CREATE TABLE collection_item (
     id SERIAL PRIMARY KEY,
 
     collection_id integer not null references attribute,
     object_id integer not null references attribute,
+    -- existing databases get this with upgrade/004_collection_item_added.sql
+    added_at timestamptz not null default now(),
 
     constraint collection_items_unique unique (collection_id, object_id)
 );
 
+-- the amount of items in every collection, kept up to date when inserting and removing items.
+-- existing databases are upgraded with upgrade/001_collection_count.sql
+CREATE TABLE collection_count (
+    collection_id integer PRIMARY KEY references attribute,
+    count bigint not null
+);
+
+-- the amount of collections every object is in, kept up to date like collection_count
+CREATE TABLE collection_object_count (
+    object_id integer PRIMARY KEY references attribute,
+    count bigint not null
+);
+
+-- existing databases get the queue columns, tables and indexes with upgrade/003_queue.sql
 CREATE TABLE queue_item (
     id SERIAL PRIMARY KEY,
     event text not null,
-    data text not null
+    data text not null,
+
+    -- items with a higher priority are claimed first
+    priority integer not null default 0,
+
+    -- optional grouping key, e.g. the destination host. groups are claimed round-robin
+    group_key text null,
+
+    -- 'pending', or 'dead' once it failed too often
+    state text not null default 'pending',
+    attempts integer not null default 0,
+
+    -- the item is not claimed before this time
+    run_after timestamptz not null default now(),
+
+    -- set while a worker has claimed the item
+    locked_until timestamptz null,
+
+    -- taken from queue_lease_seq on every claim. only the worker holding the latest claim can
+    -- acknowledge or fail the item
+    lease bigint null,
+
+    created_at timestamptz not null default now()
+);
+
+CREATE SEQUENCE queue_lease_seq;
+
+-- idempotency keys of queue items, kept for a while after the item itself is gone
+CREATE TABLE queue_key (
+    key text PRIMARY KEY,
+    item_id integer not null,
+    created_at timestamptz not null default now()
+);
+
+-- when every group of queue items was last claimed from, added when its first item is queued.
+-- claims look for items group by group, and ungrouped items use ''
+CREATE TABLE queue_group (
+    group_key text PRIMARY KEY,
+    last_claimed_at timestamptz not null,
+
+    -- the amount of items in the group that are claimed, including claims that timed out but
+    -- were not claimed again yet. kept up to date on claim, ack, fail and purge. always 0 for ''
+    in_flight integer not null default 0
+);
+
+INSERT INTO queue_group (group_key, last_claimed_at) VALUES ('', now());
+
+-- events whose queue items are not claimed until resumed
+CREATE TABLE queue_pause (
+    event text PRIMARY KEY
+);
+
+-- recurring queue items, added to the queue every time they are due
+CREATE TABLE queue_schedule (
+    name text PRIMARY KEY,
+    event text not null,
+    data text not null,
+
+    -- either
+    interval_secs double precision null,
+
+    -- or
+    cron text null,
+
+    next_run timestamptz not null,
+
+    -- incremented every time the item is queued, so it is only queued once per run
+    tick bigint not null default 0
 );
 
 CREATE INDEX attribute_url on attribute (url);
 CREATE INDEX quad_quad_id on quad (quad_id);
-CREATE INDEX collection_item_collection on collection_item (collection_id);
+CREATE INDEX collection_item_collection on collection_item (collection_id, id);
+CREATE INDEX collection_item_object on collection_item (object_id, id);
+CREATE INDEX collection_item_added on collection_item (collection_id, added_at, id);
+CREATE INDEX queue_key_created on queue_key (created_at);
+CREATE INDEX queue_item_group on queue_item ((coalesce(group_key, '')), priority desc, id) where state = 'pending' and locked_until is null;
+CREATE INDEX queue_item_event on queue_item ((coalesce(group_key, '')), event, priority desc, id) where state = 'pending' and locked_until is null;
+CREATE INDEX queue_item_locked on queue_item (locked_until) where state = 'pending' and locked_until is not null;
+
+-- casts that return null instead of failing on values that match the number or date-time
+-- pattern, but are out of range (e.g. 1e1000000 or 2021-02-30T00:00:00Z).
+-- existing databases get these with upgrade/005_try_cast.sql
+CREATE FUNCTION try_numeric(value text) RETURNS numeric AS $$
+BEGIN
+    RETURN value::numeric;
+EXCEPTION WHEN data_exception THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT;
+
+CREATE FUNCTION try_timestamptz(value text) RETURNS timestamptz AS $$
+BEGIN
+    RETURN value::timestamptz;
+EXCEPTION WHEN data_exception THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql STABLE STRICT;
@@ -0,0 +1,29 @@
+-- Adds the collection_count and collection_object_count tables to a database created before
+-- they existed, and fills them from the current collection items. collection_item is locked
+-- against writes until the counts are in place, so no change to it is missed. Running it
+-- again recounts every collection.
+
+BEGIN;
+
+LOCK TABLE collection_item IN SHARE MODE;
+
+CREATE TABLE IF NOT EXISTS collection_count (
+    collection_id integer PRIMARY KEY references attribute,
+    count bigint not null
+);
+
+CREATE TABLE IF NOT EXISTS collection_object_count (
+    object_id integer PRIMARY KEY references attribute,
+    count bigint not null
+);
+
+DELETE FROM collection_count;
+DELETE FROM collection_object_count;
+
+INSERT INTO collection_count (collection_id, count)
+    SELECT collection_id, count(*) FROM collection_item GROUP BY collection_id;
+
+INSERT INTO collection_object_count (object_id, count)
+    SELECT object_id, count(*) FROM collection_item GROUP BY object_id;
+
+COMMIT;
@@ -0,0 +1,7 @@
+-- Adds the entity_version table to a database created before it existed. Every entity starts
+-- at version 0, and gets a row on its next write.
+
+CREATE TABLE IF NOT EXISTS entity_version (
+    quad_id integer PRIMARY KEY references attribute,
+    version bigint not null
+);
@@ -0,0 +1,53 @@
+-- Adds the queue columns and tables to a database created before they existed. Items already
+-- in the queue become pending, ungrouped items with priority 0 that can be claimed right away.
+-- Running it again changes nothing.
+
+BEGIN;
+
+ALTER TABLE queue_item
+    ADD COLUMN IF NOT EXISTS priority integer not null default 0,
+    ADD COLUMN IF NOT EXISTS group_key text null,
+    ADD COLUMN IF NOT EXISTS state text not null default 'pending',
+    ADD COLUMN IF NOT EXISTS attempts integer not null default 0,
+    ADD COLUMN IF NOT EXISTS run_after timestamptz not null default now(),
+    ADD COLUMN IF NOT EXISTS locked_until timestamptz null,
+    ADD COLUMN IF NOT EXISTS lease bigint null,
+    ADD COLUMN IF NOT EXISTS created_at timestamptz not null default now();
+
+CREATE SEQUENCE IF NOT EXISTS queue_lease_seq;
+
+CREATE TABLE IF NOT EXISTS queue_key (
+    key text PRIMARY KEY,
+    item_id integer not null,
+    created_at timestamptz not null default now()
+);
+
+CREATE TABLE IF NOT EXISTS queue_group (
+    group_key text PRIMARY KEY,
+    last_claimed_at timestamptz not null,
+    in_flight integer not null default 0
+);
+
+INSERT INTO queue_group (group_key, last_claimed_at) VALUES ('', now())
+    ON CONFLICT (group_key) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS queue_pause (
+    event text PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS queue_schedule (
+    name text PRIMARY KEY,
+    event text not null,
+    data text not null,
+    interval_secs double precision null,
+    cron text null,
+    next_run timestamptz not null,
+    tick bigint not null default 0
+);
+
+CREATE INDEX IF NOT EXISTS queue_key_created on queue_key (created_at);
+CREATE INDEX IF NOT EXISTS queue_item_group on queue_item ((coalesce(group_key, '')), priority desc, id) where state = 'pending' and locked_until is null;
+CREATE INDEX IF NOT EXISTS queue_item_event on queue_item ((coalesce(group_key, '')), event, priority desc, id) where state = 'pending' and locked_until is null;
+CREATE INDEX IF NOT EXISTS queue_item_locked on queue_item (locked_until) where state = 'pending' and locked_until is not null;
+
+COMMIT;
@@ -0,0 +1,15 @@
+-- Adds collection_item.added_at and the collection item indexes to a database created before
+-- they existed. When items were added was never recorded, so every existing item gets the time
+-- this script runs as its added_at, and they are ordered among each other by id. Running it
+-- again only rebuilds collection_item_collection.
+
+BEGIN;
+
+ALTER TABLE collection_item ADD COLUMN IF NOT EXISTS added_at timestamptz not null default now();
+
+DROP INDEX IF EXISTS collection_item_collection;
+CREATE INDEX collection_item_collection on collection_item (collection_id, id);
+CREATE INDEX IF NOT EXISTS collection_item_object on collection_item (object_id, id);
+CREATE INDEX IF NOT EXISTS collection_item_added on collection_item (collection_id, added_at, id);
+
+COMMIT;
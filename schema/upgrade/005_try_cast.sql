@@ -0,0 +1,18 @@
+-- Adds the try_numeric and try_timestamptz functions to a database created before they existed.
+-- Running it again replaces them.
+
+CREATE OR REPLACE FUNCTION try_numeric(value text) RETURNS numeric AS $$
+BEGIN
+    RETURN value::numeric;
+EXCEPTION WHEN data_exception THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE STRICT;
+
+CREATE OR REPLACE FUNCTION try_timestamptz(value text) RETURNS timestamptz AS $$
+BEGIN
+    RETURN value::timestamptz;
+EXCEPTION WHEN data_exception THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql STABLE STRICT;
@@ -2,7 +2,7 @@ use kroeg_cellar::{CellarConnection, CellarEntityStore};
 use kroeg_tap::{EntityStore, StoreError, StoreItem};
 use serde_json::{from_reader, Value};
 use std::env;
-use std::time::Instant;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 async fn help(val: &str) -> Result<(), StoreError> {
     eprintln!(
@@ -14,6 +14,11 @@ async fn help(val: &str) -> Result<(), StoreError> {
     eprintln!(" - set expects one on stdin");
     eprintln!("Write collections: collection (insert|delete) <collection id> <id>");
     eprintln!("Read collections: collection list <collection id>");
+    eprintln!(" - collection since <collection id> [unix time] also shows when items were added");
+    eprintln!("Inspect the queue: queue (stats|paused)");
+    eprintln!(" - queue peek [event] [state] lists the oldest items");
+    eprintln!("Manage the queue: queue requeue [event], queue (pause|resume) <event>");
+    eprintln!(" - queue purge (event|group) <value> removes items");
 
     Ok(())
 }
@@ -62,6 +67,37 @@ async fn collection_list(client: &mut CellarEntityStore<'_>, id: &str) -> Result
     Ok(())
 }
 
+async fn collection_since(
+    client: &mut CellarEntityStore<'_>,
+    id: &str,
+    since: Option<&str>,
+) -> Result<(), StoreError> {
+    let since = match since {
+        Some(since) => Some(UNIX_EPOCH + Duration::from_secs(since.parse()?)),
+        None => None,
+    };
+
+    let mut cursor = None;
+    loop {
+        let (data, next) = client
+            .read_collection_range(id.to_owned(), since, None, cursor, 100)
+            .await?;
+
+        for (item, added_at) in data {
+            println!(
+                "{}\t{}",
+                added_at.duration_since(UNIX_EPOCH)?.as_secs(),
+                item
+            );
+        }
+
+        match next {
+            Some(next) => cursor = Some(next),
+            None => return Ok(()),
+        }
+    }
+}
+
 async fn collection_remove(
     client: &mut CellarEntityStore<'_>,
     id: &str,
@@ -72,6 +108,77 @@ async fn collection_remove(
         .await
 }
 
+async fn queue_stats(client: &mut CellarEntityStore<'_>) -> Result<(), StoreError> {
+    for stats in client.queue_stats().await? {
+        println!(
+            "{}\t{}\t{}\toldest {}s",
+            stats.event,
+            stats.state,
+            stats.count,
+            stats.oldest.as_secs()
+        );
+    }
+
+    Ok(())
+}
+
+async fn queue_peek(
+    client: &mut CellarEntityStore<'_>,
+    event: Option<&str>,
+    state: Option<&str>,
+) -> Result<(), StoreError> {
+    let items = client
+        .peek_queue(event.map(str::to_owned), state.map(str::to_owned), 20)
+        .await?;
+
+    for item in items {
+        let created_at = item.created_at.duration_since(UNIX_EPOCH)?.as_secs();
+        println!(
+            "{}\t{}\t{}\tattempts {}\tgroup {}\tadded {}\t{}",
+            item.id,
+            item.event,
+            item.state,
+            item.attempts,
+            item.group.as_ref().map(String::as_str).unwrap_or("-"),
+            created_at,
+            item.data
+        );
+    }
+
+    Ok(())
+}
+
+async fn queue_requeue(
+    client: &mut CellarEntityStore<'_>,
+    event: Option<&str>,
+) -> Result<(), StoreError> {
+    let count = client.requeue_dead(event.map(str::to_owned)).await?;
+    println!("requeued {} items", count);
+
+    Ok(())
+}
+
+async fn queue_purge(
+    client: &mut CellarEntityStore<'_>,
+    event: Option<&str>,
+    group: Option<&str>,
+) -> Result<(), StoreError> {
+    let count = client
+        .purge_queue(event.map(str::to_owned), group.map(str::to_owned))
+        .await?;
+    println!("purged {} items", count);
+
+    Ok(())
+}
+
+async fn queue_paused(client: &mut CellarEntityStore<'_>) -> Result<(), StoreError> {
+    for event in client.paused_queues().await? {
+        println!("{}", event);
+    }
+
+    Ok(())
+}
+
 async fn run_code() -> Result<(), StoreError> {
     let args: Vec<_> = env::args().collect();
 
@@ -93,6 +200,23 @@ async fn run_code() -> Result<(), StoreError> {
         ["collection", "insert", id, object] => collection_insert(&mut session, id, object).await,
         ["collection", "delete", id, object] => collection_remove(&mut session, id, object).await,
         ["collection", "list", id] => collection_list(&mut session, id).await,
+        ["collection", "since", id] => collection_since(&mut session, id, None).await,
+        ["collection", "since", id, since] => {
+            collection_since(&mut session, id, Some(*since)).await
+        }
+        ["queue", "stats"] => queue_stats(&mut session).await,
+        ["queue", "peek"] => queue_peek(&mut session, None, None).await,
+        ["queue", "peek", event] => queue_peek(&mut session, Some(*event), None).await,
+        ["queue", "peek", event, state] => {
+            queue_peek(&mut session, Some(*event), Some(*state)).await
+        }
+        ["queue", "requeue"] => queue_requeue(&mut session, None).await,
+        ["queue", "requeue", event] => queue_requeue(&mut session, Some(*event)).await,
+        ["queue", "purge", "event", event] => queue_purge(&mut session, Some(*event), None).await,
+        ["queue", "purge", "group", group] => queue_purge(&mut session, None, Some(*group)).await,
+        ["queue", "pause", event] => session.pause_queue((*event).to_owned()).await,
+        ["queue", "resume", event] => session.resume_queue((*event).to_owned()).await,
+        ["queue", "paused"] => queue_paused(&mut session).await,
         _ => help(&args[0]).await,
     };
 
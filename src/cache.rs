@@ -2,25 +2,373 @@ use crate::dbquad::{DatabaseQuad, DatabaseQuadContents};
 use jsonld::rdf::{QuadContents, StringQuad};
 use kroeg_tap::StoreItem;
 use postgres_async::types::Row;
-use std::collections::HashMap;
+use postgres_async::Statement;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The amount of attributes kept in the `AttributeCache` of a `CellarConnection` by default.
+pub const DEFAULT_ATTRIBUTE_CACHE_SIZE: usize = 16384;
+
+/// The amount of entities kept in the `ObjectCache` of a `CellarConnection` by default, once it
+/// is enabled.
+pub const DEFAULT_OBJECT_CACHE_SIZE: usize = 1024;
+
+/// The amount of attributes a `CellarEntityStore` keeps in its own cache. When there are more,
+/// the cache is cleared.
+pub const ENTITY_CACHE_LIMIT: usize = 4096;
+
+/// The amount of statements kept in the `StatementCache` of a `CellarConnection` by default.
+pub const DEFAULT_STATEMENT_CACHE_SIZE: usize = 256;
+
+/// A size-bounded map, that evicts the least recently used entry when full.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            capacity,
+        }
+    }
+
+    /// Gets an entry, marking it as most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+
+        let (value, tick) = self.entries.get_mut(key)?;
+        self.order.remove(tick);
+        self.order.insert(self.tick, key.clone());
+        *tick = self.tick;
+
+        Some(value)
+    }
+
+    /// Inserts an entry, returning the entry that got evicted to make space for it, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.tick += 1;
+
+        if let Some((_, tick)) = self.entries.remove(&key) {
+            self.order.remove(&tick);
+        }
+
+        if self.capacity == 0 {
+            return Some((key, value));
+        }
+
+        let evicted = if self.entries.len() >= self.capacity {
+            let oldest = self.order.keys().next().cloned();
+            oldest.and_then(|tick| {
+                let key = self.order.remove(&tick)?;
+                let (value, _) = self.entries.remove(&key)?;
+
+                Some((key, value))
+            })
+        } else {
+            None
+        };
+
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+
+        evicted
+    }
+
+    /// Removes an entry from the cache.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, tick) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
 
+#[derive(Debug)]
+struct AttributeMap {
+    id_to_uri: LruCache<i32, String>,
+    uri_to_id: HashMap<String, i32>,
+}
+
+/// A thread-safe, size-bounded cache of attribute IDs and URIs, shared by all the
+///  `CellarEntityStore`s of a `CellarConnection`.
+#[derive(Debug)]
+pub struct AttributeCache {
+    map: Mutex<AttributeMap>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl AttributeCache {
+    pub fn new(capacity: usize) -> Self {
+        AttributeCache {
+            map: Mutex::new(AttributeMap {
+                id_to_uri: LruCache::new(capacity),
+                uri_to_id: HashMap::new(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Looks up the URI of an attribute ID.
+    pub fn get_uri(&self, id: i32) -> Option<String> {
+        let uri = self.map.lock().unwrap().id_to_uri.get(&id).cloned();
+        self.count(uri.is_some());
+
+        uri
+    }
+
+    /// Looks up the attribute ID of an URI.
+    pub fn get_id(&self, uri: &str) -> Option<i32> {
+        let mut map = self.map.lock().unwrap();
+        let id = map.uri_to_id.get(uri).cloned();
+        if let Some(id) = id {
+            map.id_to_uri.get(&id);
+        }
+
+        self.count(id.is_some());
+
+        id
+    }
+
+    pub fn insert(&self, id: i32, uri: String) {
+        let mut map = self.map.lock().unwrap();
+
+        map.uri_to_id.insert(uri.to_owned(), id);
+        if let Some((_, evicted)) = map.id_to_uri.insert(id, uri) {
+            map.uri_to_id.remove(&evicted);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.lock().unwrap().id_to_uri.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The amount of lookups that were answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The amount of lookups that had to go to the database.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn count(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A thread-safe, size-bounded cache of entities, shared by all the `CellarEntityStore`s of a
+///  `CellarConnection`. Entries are removed when written or deleted through any store on the
+///  connection, and read again from the database on the next lookup. Changes made by other
+///  processes are only seen while the connection is listening for invalidations, so the cache
+///  starts out disabled, and is enabled by `CellarConnection::listen_invalidations`.
+#[derive(Debug)]
+pub struct ObjectCache {
+    items: Mutex<LruCache<String, StoreItem>>,
+    enabled: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ObjectCache {
+    /// Creates a disabled cache, that keeps at most `capacity` entities once enabled.
+    pub fn new(capacity: usize) -> Self {
+        ObjectCache {
+            items: Mutex::new(LruCache::new(capacity)),
+            enabled: AtomicBool::new(false),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Enables or disables the cache. A disabled cache is emptied, and does not keep anything
+    ///  inserted into it. Only enable it by hand if no other process writes to the database.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut items = self.items.lock().unwrap();
+        self.enabled.store(enabled, Ordering::SeqCst);
+
+        if !enabled {
+            let capacity = items.capacity;
+            *items = LruCache::new(capacity);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn get(&self, path: &str) -> Option<StoreItem> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let item = self.items.lock().unwrap().get(&path.to_owned()).cloned();
+        if item.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        item
+    }
+
+    pub fn insert(&self, path: String, item: StoreItem) {
+        let mut items = self.items.lock().unwrap();
+        if self.is_enabled() {
+            items.insert(path, item);
+        }
+    }
+
+    pub fn remove(&self, path: &str) {
+        self.items.lock().unwrap().remove(&path.to_owned());
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The amount of lookups that were answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The amount of lookups that had to go to the database.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+struct PreparedStatements {
+    statements: LruCache<String, Arc<Statement<'static>>>,
+
+    // evicted statements that were still being used, and cannot be closed yet
+    retired: Vec<Arc<Statement<'static>>>,
+}
+
+/// A thread-safe, size-bounded cache of prepared statements, keyed by their SQL text. Statements
+///  that are evicted have to be closed by whoever inserted the statement that evicted them.
+pub struct StatementCache {
+    statements: Mutex<PreparedStatements>,
+}
+
+impl StatementCache {
+    pub fn new(capacity: usize) -> Self {
+        StatementCache {
+            statements: Mutex::new(PreparedStatements {
+                statements: LruCache::new(capacity),
+                retired: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn get(&self, query: &str) -> Option<Arc<Statement<'static>>> {
+        self.statements
+            .lock()
+            .unwrap()
+            .statements
+            .get(&query.to_owned())
+            .cloned()
+    }
+
+    /// Inserts a statement, returning the evicted statements that are no longer in use, and
+    ///  should be closed.
+    pub fn insert(
+        &self,
+        query: String,
+        statement: Arc<Statement<'static>>,
+    ) -> Vec<Statement<'static>> {
+        let mut map = self.statements.lock().unwrap();
+
+        // another store may have prepared the same query in the meantime
+        if let Some(previous) = map.statements.remove(&query) {
+            map.retired.push(previous);
+        }
+
+        if let Some((_, evicted)) = map.statements.insert(query, statement) {
+            map.retired.push(evicted);
+        }
+
+        let mut closable = Vec::new();
+        for statement in std::mem::replace(&mut map.retired, Vec::new()) {
+            match Arc::try_unwrap(statement) {
+                Ok(statement) => closable.push(statement),
+                Err(statement) => map.retired.push(statement),
+            }
+        }
+
+        closable
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.lock().unwrap().statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The per-store cache, sitting in front of the `AttributeCache` of the connection. It is
+///  cleared whenever it grows past `ENTITY_CACHE_LIMIT`, so long-lived stores stay bounded.
 #[derive(Debug)]
 pub struct EntityCache {
     pub id_to_uri: HashMap<i32, String>,
     pub uri_to_id: HashMap<String, i32>,
-
-    pub object: HashMap<String, StoreItem>,
 }
 
 impl EntityCache {
-    pub fn cache_attribute_row(&mut self, row: Row) {
+    /// Caches an attribute row, returning the ID and URI it contains.
+    pub fn cache_attribute_row(&mut self, row: Row) -> (i32, String) {
         let id: i32 = row.get(0).unwrap().unwrap();
         let uri: String = row.get(1).unwrap().unwrap();
 
+        self.cache_attribute(id, uri.to_owned());
+
+        (id, uri)
+    }
+
+    pub fn cache_attribute(&mut self, id: i32, uri: String) {
         self.id_to_uri.insert(id, uri.to_owned());
         self.uri_to_id.insert(uri, id);
     }
 
+    /// Clears the attributes if there are more than `ENTITY_CACHE_LIMIT`. This may only be
+    /// called before looking up the attributes needed for an operation, as any attribute
+    /// cached before is gone afterwards.
+    pub(crate) fn trim(&mut self) {
+        if self.id_to_uri.len() > ENTITY_CACHE_LIMIT {
+            self.id_to_uri.clear();
+            self.uri_to_id.clear();
+        }
+    }
+
     pub fn translate_quad(&self, quad: DatabaseQuad) -> StringQuad {
         let contents = match quad.contents {
             DatabaseQuadContents::Id(id) => QuadContents::Id(self.id_to_uri[&id].clone()),
@@ -45,7 +393,140 @@ impl EntityCache {
         EntityCache {
             id_to_uri: HashMap::new(),
             uri_to_id: HashMap::new(),
-            object: HashMap::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        assert!(cache.insert(1, "a").is_none());
+        assert!(cache.insert(2, "b").is_none());
+
+        // touching 1 makes 2 the least recently used entry
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.insert(3, "c"), Some((2, "b")));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+    }
+
+    #[test]
+    fn lru_replacing_does_not_evict() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+
+        assert!(cache.insert(1, "c").is_none());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&1), Some(&"c"));
+
+        // the replaced entry became the most recently used one
+        assert_eq!(cache.insert(3, "d"), Some((2, "b")));
+    }
+
+    #[test]
+    fn lru_never_exceeds_capacity() {
+        let mut cache = LruCache::new(3);
+        for i in 0..100 {
+            cache.insert(i, i);
+            assert!(cache.len() <= 3);
+        }
+
+        assert_eq!(cache.get(&96), None);
+        assert_eq!(cache.get(&97), Some(&97));
+        assert_eq!(cache.get(&99), Some(&99));
+    }
+
+    #[test]
+    fn lru_remove_frees_space() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+
+        assert_eq!(cache.remove(&1), Some("a"));
+        assert_eq!(cache.remove(&1), None);
+        assert!(cache.insert(3, "c").is_none());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn attribute_cache_evicts_both_directions() {
+        let cache = AttributeCache::new(1);
+        cache.insert(1, "https://example.com/a".to_owned());
+        cache.insert(2, "https://example.com/b".to_owned());
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get_id("https://example.com/a"), None);
+        assert_eq!(cache.get_uri(1), None);
+        assert_eq!(cache.get_id("https://example.com/b"), Some(2));
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 2);
+    }
+
+    fn item(path: &str) -> StoreItem {
+        let value = serde_json::json!([{ "@id": path }]);
+
+        StoreItem::parse(path, &value).unwrap()
+    }
+
+    #[test]
+    fn object_cache_evicts_least_recently_used() {
+        let cache = ObjectCache::new(2);
+        cache.set_enabled(true);
+        cache.insert("a".to_owned(), item("https://example.com/a"));
+        cache.insert("b".to_owned(), item("https://example.com/b"));
+
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_owned(), item("https://example.com/c"));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.hits(), 2);
+        assert_eq!(cache.misses(), 2);
+    }
+
+    #[test]
+    fn object_cache_only_keeps_entries_while_enabled() {
+        let cache = ObjectCache::new(2);
+        cache.insert("a".to_owned(), item("https://example.com/a"));
+        assert_eq!(cache.len(), 0);
+
+        cache.set_enabled(true);
+        cache.insert("a".to_owned(), item("https://example.com/a"));
+        assert!(cache.get("a").is_some());
+
+        // disabling drops what was cached while the invalidations were followed
+        cache.set_enabled(false);
+        assert_eq!(cache.len(), 0);
+        cache.set_enabled(true);
+        assert!(cache.get("a").is_none());
+
+        let empty = ObjectCache::new(0);
+        empty.set_enabled(true);
+        empty.insert("a".to_owned(), item("https://example.com/a"));
+        assert_eq!(empty.len(), 0);
+    }
+
+    #[test]
+    fn entity_cache_is_cleared_past_its_limit() {
+        let mut cache = EntityCache::new();
+        for i in 0..=ENTITY_CACHE_LIMIT as i32 {
+            cache.cache_attribute(i, format!("https://example.com/{}", i));
+        }
+
+        cache.trim();
+        assert!(cache.id_to_uri.is_empty());
+        assert!(cache.uri_to_id.is_empty());
+    }
+}
@@ -2,12 +2,31 @@ use async_std::net::TcpStream;
 use postgres_async::types::AnyError;
 use postgres_async::Connection;
 
+use crate::cache::{
+    AttributeCache, ObjectCache, StatementCache, DEFAULT_ATTRIBUTE_CACHE_SIZE,
+    DEFAULT_OBJECT_CACHE_SIZE, DEFAULT_STATEMENT_CACHE_SIZE,
+};
+use crate::listener::{CellarListener, ENTITY_CHANNEL};
 use crate::statements::Statements;
 
 /// A connection to a Kroeg PostgreSQL-backed database.
+///
+/// Entities read through the connection are kept in its `ObjectCache` while
+///  `listen_invalidations` is running for this connection, so changes made through other
+///  connections, or by other processes, are seen. Without it, every entity is read from the
+///  database.
 pub struct CellarConnection {
     pub connection: Connection<'static>,
     pub statements: Statements<'static>,
+
+    /// Attribute IDs and URIs, shared by every `CellarEntityStore` on this connection.
+    pub attributes: AttributeCache,
+
+    /// Recently used entities, shared by every `CellarEntityStore` on this connection.
+    pub objects: ObjectCache,
+
+    /// Statements prepared by `CellarEntityStore::do_query`, keyed by their SQL text.
+    pub prepared: StatementCache,
 }
 
 impl CellarConnection {
@@ -28,6 +47,31 @@ impl CellarConnection {
         Ok(CellarConnection {
             connection,
             statements,
+            attributes: AttributeCache::new(DEFAULT_ATTRIBUTE_CACHE_SIZE),
+            objects: ObjectCache::new(DEFAULT_OBJECT_CACHE_SIZE),
+            prepared: StatementCache::new(DEFAULT_STATEMENT_CACHE_SIZE),
         })
     }
+
+    /// Enables the object cache, and removes entities changed by other processes from it, as
+    ///  they are notified on the listener. This only returns if the listener fails, after
+    ///  disabling the cache again, so it should be spawned as a task.
+    pub async fn listen_invalidations(&self, listener: &CellarListener) -> Result<(), AnyError> {
+        listener.listen(ENTITY_CHANNEL).await?;
+        self.objects.set_enabled(true);
+
+        let result = self.follow_invalidations(listener).await;
+        self.objects.set_enabled(false);
+
+        result
+    }
+
+    async fn follow_invalidations(&self, listener: &CellarListener) -> Result<(), AnyError> {
+        loop {
+            let notification = listener.next().await?;
+            if notification.channel == ENTITY_CHANNEL {
+                self.objects.remove(&notification.payload);
+            }
+        }
+    }
 }
@@ -1,10 +1,20 @@
 use jsonld::rdf::StringQuad;
+use kroeg_tap::StoreItem;
 use postgres_async::types::{AnyError, Row};
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
 use crate::cache::EntityCache;
+use crate::cron::Schedule;
 use crate::dbquad::{collect_quad_ids, DatabaseQuad};
-use crate::types::CollectionItem;
+use crate::queuestore::QueueOptions;
+use crate::types::{
+    time_to_epoch, CollectionItem, Enqueued, LeaseLost, OrderedKey, QueueEntry, QueueItemInfo,
+    QueueJob, QueueStats, RecurringJob, DATETIME_PATTERN, DATETIME_TYPES, NUMBER_PATTERN,
+    NUMERIC_TYPES,
+};
 use crate::CellarConnection;
 
 /// A wrapper for a CellarConnection that implements the EntityStore and QueueStore traits.
@@ -13,12 +23,18 @@ use crate::CellarConnection;
 pub struct CellarEntityStore<'a> {
     connection: &'a CellarConnection,
     pub cache: EntityCache,
+    pub queue_options: QueueOptions,
+
+    /// The leases of the queue items claimed through `QueueStore::get_item`, and when their
+    /// visibility timeout passes, by item ID.
+    pub(crate) leases: HashMap<i32, (i64, Instant)>,
 }
 
 impl<'a> fmt::Debug for CellarEntityStore<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CellarEntityStore")
             .field("cache", &self.cache)
+            .field("queue_options", &self.queue_options)
             .finish()
     }
 }
@@ -28,6 +44,8 @@ impl<'a> CellarEntityStore<'a> {
         CellarEntityStore {
             connection,
             cache: EntityCache::new(),
+            queue_options: QueueOptions::default(),
+            leases: HashMap::new(),
         }
     }
 
@@ -46,12 +64,22 @@ impl<'a> CellarEntityStore<'a> {
             .collect())
     }
 
-    /// Takes a slice of Strings, queries them into the database, then stores them into the cache
+    /// Takes a slice of Strings, queries them into the database, then stores them into the cache.
+    /// Attributes cached by earlier calls may be evicted by this.
     pub async fn cache_uris(&mut self, uris: &[String]) -> Result<(), AnyError> {
-        let uncached: Vec<_> = uris
-            .iter()
-            .filter(|&f| !self.cache.uri_to_id.contains_key(f))
-            .collect();
+        self.cache.trim();
+
+        let mut uncached = Vec::new();
+        for uri in uris {
+            if self.cache.uri_to_id.contains_key(uri) {
+                continue;
+            }
+
+            match self.connection.attributes.get_id(uri) {
+                Some(id) => self.cache.cache_attribute(id, uri.to_owned()),
+                None => uncached.push(uri),
+            }
+        }
 
         if uncached.is_empty() {
             return Ok(());
@@ -68,7 +96,8 @@ impl<'a> CellarEntityStore<'a> {
         while let Some(item) = query.next().await {
             let item = item?;
 
-            self.cache.cache_attribute_row(item);
+            let (id, uri) = self.cache.cache_attribute_row(item);
+            self.connection.attributes.insert(id, uri);
         }
 
         Ok(())
@@ -76,10 +105,17 @@ impl<'a> CellarEntityStore<'a> {
 
     /// Takes a slice of IDs, queries them from the database, and stores them into the cache.
     pub async fn cache_ids(&mut self, ids: &[i32]) -> Result<(), AnyError> {
-        let uncached: Vec<_> = ids
-            .iter()
-            .filter(|f| !self.cache.id_to_uri.contains_key(f))
-            .collect();
+        let mut uncached = Vec::new();
+        for &id in ids {
+            if self.cache.id_to_uri.contains_key(&id) {
+                continue;
+            }
+
+            match self.connection.attributes.get_uri(id) {
+                Some(uri) => self.cache.cache_attribute(id, uri),
+                None => uncached.push(id),
+            }
+        }
 
         if uncached.is_empty() {
             return Ok(());
@@ -96,7 +132,8 @@ impl<'a> CellarEntityStore<'a> {
         while let Some(item) = query.next().await {
             let item = item?;
 
-            self.cache.cache_attribute_row(item);
+            let (id, uri) = self.cache.cache_attribute_row(item);
+            self.connection.attributes.insert(id, uri);
         }
 
         Ok(())
@@ -120,7 +157,22 @@ impl<'a> CellarEntityStore<'a> {
         Ok(out)
     }
 
-    /// Removes all the quads stored for a specific quad ID.
+    /// Removes an entity from the object cache of the connection.
+    pub fn invalidate(&mut self, path: &str) {
+        self.connection.objects.remove(path);
+    }
+
+    /// Stores an entity into the object cache of the connection.
+    pub fn cache_object(&mut self, path: String, item: StoreItem) {
+        self.connection.objects.insert(path, item);
+    }
+
+    /// Looks up an entity in the object cache of the connection.
+    pub fn cached_object(&mut self, path: &str) -> Option<StoreItem> {
+        self.connection.objects.get(path)
+    }
+
+    /// Removes all the quads stored for a specific quad ID, and bumps its version.
     pub async fn delete_quad(&mut self, id: i32) -> Result<(), AnyError> {
         let mut bound = self
             .connection
@@ -130,13 +182,22 @@ impl<'a> CellarEntityStore<'a> {
             .await?;
         let mut query = bound.execute(&self.connection.connection).await?;
 
+        let mut changed = Vec::new();
         while let Some(item) = query.next().await {
-            item?;
+            let uri: String = item?.get(0)?.unwrap();
+            changed.push(uri);
+        }
+
+        // removed after the write, so a concurrent read cannot cache the old entity again
+        for uri in changed {
+            self.invalidate(&uri);
         }
 
         Ok(())
     }
 
+    /// Inserts quads, passed as one array per column, starting with the quad ID. Bumps the
+    /// version of every quad ID written to.
     pub async fn insert_quad(
         &mut self,
         data: &[&dyn postgres_async::types::Serializable],
@@ -149,13 +210,67 @@ impl<'a> CellarEntityStore<'a> {
             .await?;
         let mut query = bound.execute(&self.connection.connection).await?;
 
+        let mut changed = Vec::new();
         while let Some(item) = query.next().await {
-            item?;
+            let uri: String = item?.get(0)?.unwrap();
+            changed.push(uri);
+        }
+
+        // removed after the write, so a concurrent read cannot cache the old entity again
+        for uri in changed {
+            self.invalidate(&uri);
         }
 
         Ok(())
     }
 
+    /// Deletes the quads with the passed row IDs, and inserts the new quads, in one statement.
+    /// `data` starts with the quad ID, the expected version and the row IDs to delete, followed by
+    /// the columns of `insert_quad` minus the quad ID. Nothing is changed if the stored version
+    /// differs from the expected one. Returns the new version, or `None` on a version mismatch.
+    pub async fn update_quads(
+        &mut self,
+        data: &[&dyn postgres_async::types::Serializable],
+    ) -> Result<Option<i64>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .update_quads
+            .bind(&self.connection.connection, data)
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut version = None;
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            version = item.get(0)?;
+        }
+
+        Ok(version)
+    }
+
+    /// Reads the version of the quads stored for a specific quad ID. Quads that have never been
+    /// written are at version 0.
+    pub async fn read_version(&mut self, id: i32) -> Result<i64, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .select_version
+            .bind(&self.connection.connection, &[&id])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut version = 0;
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            version = item.get(0)?.unwrap();
+        }
+
+        Ok(version)
+    }
+
     pub async fn insert_collection(
         &mut self,
         collection: i32,
@@ -229,61 +344,416 @@ impl<'a> CellarEntityStore<'a> {
         Ok(out)
     }
 
+    /// Inserts many (collection, object) pairs at once, passed as two arrays of equal length.
+    /// Returns the pairs that were not in their collection yet.
+    pub async fn insert_collection_many(
+        &mut self,
+        collections: Vec<i32>,
+        objects: Vec<i32>,
+    ) -> Result<Vec<(i32, i32)>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .insert_collection_many
+            .bind(&self.connection.connection, &[&collections, &objects])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            out.push((item.get(0)?.unwrap(), item.get(1)?.unwrap()));
+        }
+
+        Ok(out)
+    }
+
+    /// Deletes many (collection, object) pairs at once, passed as two arrays of equal length.
+    /// Returns the pairs that were actually in their collection.
+    pub async fn delete_collection_many(
+        &mut self,
+        collections: Vec<i32>,
+        objects: Vec<i32>,
+    ) -> Result<Vec<(i32, i32)>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .delete_collection_many
+            .bind(&self.connection.connection, &[&collections, &objects])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            out.push((item.get(0)?.unwrap(), item.get(1)?.unwrap()));
+        }
+
+        Ok(out)
+    }
+
+    /// Reads the newest items of a collection that were added in a time range, newest first.
+    /// `since` is inclusive and `until` exclusive, and either can be left open. Items are ordered
+    /// on when they were added and their row, and `after` continues after the item with that
+    /// (added_at in microseconds since the UNIX epoch, row) key. Every item is returned with
+    /// its key.
+    pub async fn select_collection_range(
+        &mut self,
+        collection: i32,
+        since: Option<SystemTime>,
+        until: Option<SystemTime>,
+        after: Option<(i64, i32)>,
+        limit: i32,
+    ) -> Result<Vec<(CollectionItem, (i64, i32))>, AnyError> {
+        let since = since.map(time_to_epoch);
+        let until = until.map(time_to_epoch);
+        let after_time = after.map(|(time, _)| time);
+        let after_id = after.map(|(_, id)| id);
+        let mut bound = self
+            .connection
+            .statements
+            .select_collection_range
+            .bind(
+                &self.connection.connection,
+                &[
+                    &collection,
+                    &since,
+                    &until,
+                    &(limit as i64),
+                    &after_time,
+                    &after_id,
+                ],
+            )
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            let row = item?;
+            let item = CollectionItem::make_from_row(&row);
+            let key = (row.get(4)?.unwrap(), item.id);
+
+            out.push((item, key));
+        }
+
+        Ok(out)
+    }
+
+    /// Reads the collections containing an object, paginated on the collection row like
+    /// `select_collection`. If `prefix` is set, only collections whose URI starts with it are read.
     pub async fn select_collection_inverse(
         &mut self,
         object: i32,
+        offset: i32,
+        limit: i32,
+        until: bool,
+        prefix: &Option<String>,
     ) -> Result<Vec<CollectionItem>, AnyError> {
         let mut out = Vec::new();
 
+        let mut bound = if until {
+            &self.connection.statements.select_collection_inverse
+        } else {
+            &self.connection.statements.select_collection_inverse_forward
+        }
+        .bind(
+            &self.connection.connection,
+            &[&object, &offset, &(limit as i64), prefix],
+        )
+        .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            out.push(CollectionItem::make_from_row(&item));
+        }
+
+        if !until {
+            out.reverse();
+        }
+
+        Ok(out)
+    }
+
+    /// Reads the amount of items in a collection, as kept up to date by `insert_collection` and
+    /// `delete_collection`.
+    pub async fn collection_count(&mut self, collection: i32) -> Result<i64, AnyError> {
         let mut bound = self
             .connection
             .statements
-            .select_collection_inverse
-            .bind(&self.connection.connection, &[&object])
+            .select_collection_count
+            .bind(&self.connection.connection, &[&collection])
             .await?;
         let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut count = 0;
+        while let Some(item) = query.next().await {
+            count = item?.get(0)?.unwrap();
+        }
+
+        Ok(count)
+    }
+
+    /// Reads the items of `collection` that are also in `other`, or with `difference` set, the
+    /// ones that are not. Paginated on the row of `collection` like `select_collection`.
+    pub async fn select_collection_intersect(
+        &mut self,
+        collection: i32,
+        other: i32,
+        offset: i32,
+        limit: i32,
+        until: bool,
+        difference: bool,
+    ) -> Result<Vec<CollectionItem>, AnyError> {
+        let mut out = Vec::new();
+
+        let mut bound = if until {
+            &self
+                .connection
+                .statements
+                .select_collection_intersect_reverse
+        } else {
+            &self.connection.statements.select_collection_intersect
+        }
+        .bind(
+            &self.connection.connection,
+            &[&collection, &other, &offset, &(limit as i64), &difference],
+        )
+        .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
         while let Some(item) = query.next().await {
             let item = item?;
 
-            out.push(CollectionItem {
-                id: item.get(0)?.unwrap(),
-                collection_id: item.get(1)?.unwrap(),
-                object_id: item.get(2)?.unwrap(),
-            });
+            out.push(CollectionItem::make_from_row(&item));
+        }
+
+        if !until {
+            out.reverse();
         }
 
         Ok(out)
     }
 
-    pub async fn collection_contains(
+    /// Reads the items of several collections as one, ordered by their row and paginated like
+    /// `select_collection`.
+    pub async fn select_collection_merged(
+        &mut self,
+        collections: Vec<i32>,
+        offset: i32,
+        limit: i32,
+        until: bool,
+    ) -> Result<Vec<CollectionItem>, AnyError> {
+        let mut out = Vec::new();
+
+        let mut bound = if until {
+            &self.connection.statements.select_collection_merged_reverse
+        } else {
+            &self.connection.statements.select_collection_merged
+        }
+        .bind(
+            &self.connection.connection,
+            &[&collections, &offset, &(limit as i64)],
+        )
+        .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            out.push(CollectionItem::make_from_row(&item));
+        }
+
+        if !until {
+            out.reverse();
+        }
+
+        Ok(out)
+    }
+
+    /// Reads the items of a collection ordered by the value of `predicate` on each item, then by
+    /// their row. Values typed as an XSD number are compared as numbers, and values typed as
+    /// `xsd:dateTime` as points in time, unless they are out of range for their type, like
+    /// `2021-02-30T00:00:00Z`, in which case they are compared as strings. Items without the
+    /// predicate sort as an empty string, and items with several values by the lowest one.
+    /// Reads the items after (or before, if `until` is false) the key, exclusive, or from the
+    /// end of the collection if there is none. Returns every item together with its key.
+    pub async fn select_collection_ordered(
         &mut self,
         collection: i32,
-        item: i32,
-    ) -> Result<bool, AnyError> {
+        predicate: i32,
+        key: Option<OrderedKey>,
+        limit: i32,
+        until: bool,
+    ) -> Result<Vec<(CollectionItem, OrderedKey)>, AnyError> {
+        let mut out = Vec::new();
+
+        let (value, id, class, number) = match key {
+            Some(key) => (Some(key.value), key.id, key.class, Some(key.number)),
+            None => (None, 0, 0, None),
+        };
+        let numeric_types: Vec<_> = NUMERIC_TYPES.iter().map(|f| f.to_string()).collect();
+        let datetime_types: Vec<_> = DATETIME_TYPES.iter().map(|f| f.to_string()).collect();
+
+        let mut bound = if until {
+            &self.connection.statements.select_collection_ordered_reverse
+        } else {
+            &self.connection.statements.select_collection_ordered
+        }
+        .bind(
+            &self.connection.connection,
+            &[
+                &collection,
+                &predicate,
+                &value,
+                &id,
+                &(limit as i64),
+                &numeric_types,
+                &datetime_types,
+                &NUMBER_PATTERN.to_owned(),
+                &DATETIME_PATTERN.to_owned(),
+                &class,
+                &number,
+            ],
+        )
+        .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+        while let Some(item) = query.next().await {
+            let item = item?;
+            let row = CollectionItem::make_from_row(&item);
+            let key = OrderedKey {
+                value: item.get(4)?.unwrap(),
+                class: item.get(5)?.unwrap(),
+                number: item.get(6)?.unwrap(),
+                id: row.id,
+            };
+
+            out.push((row, key));
+        }
+
+        if !until {
+            out.reverse();
+        }
+
+        Ok(out)
+    }
+
+    /// Counts the distinct objects in any of the collections.
+    pub async fn collection_union_count(&mut self, collections: Vec<i32>) -> Result<i64, AnyError> {
         let mut bound = self
             .connection
             .statements
-            .find_collection
-            .bind(&self.connection.connection, &[&collection, &item])
+            .select_collection_union_count
+            .bind(&self.connection.connection, &[&collections])
             .await?;
         let mut query = bound.execute(&self.connection.connection).await?;
 
-        let mut contains = false;
+        let mut count = 0;
         while let Some(item) = query.next().await {
-            item?;
+            count = item?.get(0)?.unwrap();
+        }
+
+        Ok(count)
+    }
+
+    /// Returns which of the objects are in the collection, in no particular order.
+    pub async fn select_collection_members(
+        &mut self,
+        collection: i32,
+        objects: Vec<i32>,
+    ) -> Result<Vec<i32>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .select_collection_members
+            .bind(&self.connection.connection, &[&collection, &objects])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
 
-            contains = true;
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            out.push(item?.get(0)?.unwrap());
         }
 
-        Ok(contains)
+        Ok(out)
     }
 
+    /// Counts the amount of collections containing an object, optionally only those whose URI
+    /// starts with `prefix`. Without a prefix, this reads the count kept in
+    /// `collection_object_count`; with one, the matching collections have to be counted.
+    pub async fn collection_inverse_count(
+        &mut self,
+        object: i32,
+        prefix: &Option<String>,
+    ) -> Result<i64, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .select_collection_inverse_count
+            .bind(&self.connection.connection, &[&object, prefix])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut count = 0;
+        while let Some(item) = query.next().await {
+            count = item?.get(0)?.unwrap();
+        }
+
+        Ok(count)
+    }
+
+    /// Finds the row of an object in a collection, if it is in there.
+    pub async fn find_collection_item(
+        &mut self,
+        collection: i32,
+        object: i32,
+    ) -> Result<Option<CollectionItem>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .find_collection
+            .bind(&self.connection.connection, &[&collection, &object])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut output = None;
+        while let Some(item) = query.next().await {
+            output = Some(CollectionItem::make_from_row(&item?));
+        }
+
+        Ok(output)
+    }
+
+    pub async fn collection_contains(
+        &mut self,
+        collection: i32,
+        item: i32,
+    ) -> Result<bool, AnyError> {
+        Ok(self.find_collection_item(collection, item).await?.is_some())
+    }
+
+    /// Runs a query, returning all its rows. The prepared statement is kept in the connection,
+    /// so running the same SQL text again skips parsing it.
     pub async fn do_query(
         &mut self,
         q: String,
         data: &[&dyn postgres_async::types::Serializable],
     ) -> Result<Vec<Row>, AnyError> {
-        let statement = postgres_async::Statement::parse(&self.connection.connection, &q).await?;
+        let statement = match self.connection.prepared.get(&q) {
+            Some(statement) => statement,
+            None => {
+                let statement = Arc::new(
+                    postgres_async::Statement::parse(&self.connection.connection, &q).await?,
+                );
+
+                for evicted in self.connection.prepared.insert(q, statement.clone()) {
+                    evicted.close(&self.connection.connection).await?;
+                }
+
+                statement
+            }
+        };
+
         let mut bound = statement.bind(&self.connection.connection, data).await?;
         let mut query = bound.execute(&self.connection.connection).await?;
 
@@ -295,13 +765,42 @@ impl<'a> CellarEntityStore<'a> {
         Ok(out)
     }
 
-    pub async fn pop_queue(&mut self) -> Result<Option<(String, String)>, AnyError> {
-        let mut bound = self
-            .connection
-            .statements
-            .queue_item_pop
-            .bind(&self.connection.connection, &[])
-            .await?;
+    /// Claims the pending queue item with the highest priority, hiding it from other workers until
+    /// it is acknowledged or its visibility timeout passes. Within a priority, items whose timeout
+    /// passed are claimed again first, then groups are claimed round-robin, with ungrouped items
+    /// taking turns as one group. Items whose timeout passed too often are dead-lettered.
+    pub async fn pop_queue(&mut self) -> Result<Option<QueueEntry>, AnyError> {
+        let timeout = self.queue_options.visibility_timeout.as_secs_f64();
+        let statements = &self.connection.statements;
+        let mut bound = match &self.queue_options.events {
+            Some(events) => {
+                statements
+                    .queue_item_claim_events
+                    .bind(
+                        &self.connection.connection,
+                        &[
+                            &timeout,
+                            &self.queue_options.max_attempts,
+                            events,
+                            &self.queue_options.max_in_flight_per_group,
+                        ],
+                    )
+                    .await?
+            }
+            None => {
+                statements
+                    .queue_item_claim
+                    .bind(
+                        &self.connection.connection,
+                        &[
+                            &timeout,
+                            &self.queue_options.max_attempts,
+                            &self.queue_options.max_in_flight_per_group,
+                        ],
+                    )
+                    .await?
+            }
+        };
         let mut query = bound.execute(&self.connection.connection).await?;
         let mut output = None;
 
@@ -309,19 +808,284 @@ impl<'a> CellarEntityStore<'a> {
             let item = item?;
 
             if output.is_none() {
-                output = Some((item.get(0)?.unwrap(), item.get(1)?.unwrap()));
+                output = Some(QueueEntry::make_from_row(&item));
             }
         }
 
         Ok(output)
     }
 
+    /// Removes a claimed queue item, after it has been handled. Fails with `LeaseLost` if the
+    /// claim timed out and the item was claimed again, or is gone. This also forgets a batch of
+    /// idempotency keys that are older than the deduplication window.
+    pub async fn ack_queue(&mut self, id: i32, lease: i64) -> Result<(), AnyError> {
+        let window = self.queue_options.dedup_window.as_secs_f64();
+        let mut bound = self
+            .connection
+            .statements
+            .queue_item_ack
+            .bind(&self.connection.connection, &[&id, &lease, &window])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut found = false;
+        while let Some(item) = query.next().await {
+            item?;
+            found = true;
+        }
+
+        if !found {
+            return Err(LeaseLost { id, lease }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Releases a claimed queue item after a failure, so it can be retried after an exponential
+    /// backoff. If it has been attempted `max_attempts` times, it is dead-lettered instead.
+    /// Fails with `LeaseLost` like `ack_queue`.
+    pub async fn fail_queue(&mut self, id: i32, lease: i64) -> Result<(), AnyError> {
+        let base = self.queue_options.backoff_base.as_secs_f64();
+        let max = self.queue_options.backoff_max.as_secs_f64();
+        let mut bound = self
+            .connection
+            .statements
+            .queue_item_fail
+            .bind(
+                &self.connection.connection,
+                &[&id, &self.queue_options.max_attempts, &base, &max, &lease],
+            )
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut found = false;
+        while let Some(item) = query.next().await {
+            item?;
+            found = true;
+        }
+
+        if !found {
+            return Err(LeaseLost { id, lease }.into());
+        }
+
+        Ok(())
+    }
+
     pub async fn push_queue(&mut self, event: String, data: String) -> Result<(), AnyError> {
+        self.enqueue(QueueJob::new(event, data)).await?;
+
+        Ok(())
+    }
+
+    /// Adds a new item to the queue. If the job has an idempotency key that was used within the
+    /// deduplication window, the existing item is returned instead.
+    pub async fn enqueue(&mut self, job: QueueJob) -> Result<Enqueued, AnyError> {
+        let run_after = job.run_after_epoch();
+        let window = self.queue_options.dedup_window.as_secs_f64();
         let mut bound = self
             .connection
             .statements
             .queue_item_put
-            .bind(&self.connection.connection, &[&event, &data])
+            .bind(
+                &self.connection.connection,
+                &[
+                    &job.event,
+                    &job.data,
+                    &run_after,
+                    &job.priority,
+                    &job.group,
+                    &job.key,
+                    &window,
+                ],
+            )
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut output = None;
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            output = Some(Enqueued {
+                id: item.get(0)?.unwrap(),
+                added: item.get(1)?.unwrap(),
+                exists: item.get(2)?.unwrap(),
+            });
+        }
+
+        if let Some(output) = output {
+            return Ok(output);
+        }
+
+        // the key was claimed by a concurrent enqueue, which wasn't visible to the statement yet.
+        match job.key {
+            Some(key) => {
+                let (id, exists) = self.select_queue_key(&key).await?;
+
+                Ok(Enqueued {
+                    id,
+                    added: false,
+                    exists,
+                })
+            }
+            None => Err("queue item was not inserted".into()),
+        }
+    }
+
+    /// Looks up the ID of the queue item that was added with an idempotency key, and whether
+    /// that item is still in the queue.
+    pub async fn select_queue_key(&mut self, key: &str) -> Result<(i32, bool), AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .queue_key_select
+            .bind(&self.connection.connection, &[&key.to_owned()])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut output = None;
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            output = Some((item.get(0)?.unwrap(), item.get(1)?.unwrap()));
+        }
+
+        output.ok_or_else(|| "unknown queue item key".into())
+    }
+
+    /// Forgets all idempotency keys that are older than the deduplication window. Acknowledging
+    /// items already forgets them in small batches, so this is only needed to catch up, e.g.
+    /// after a burst of keyed items.
+    pub async fn prune_queue_keys(&mut self) -> Result<(), AnyError> {
+        let window = self.queue_options.dedup_window.as_secs_f64();
+        let mut bound = self
+            .connection
+            .statements
+            .queue_key_prune
+            .bind(&self.connection.connection, &[&window])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        while let Some(item) = query.next().await {
+            item?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a new item to the queue, that will not be claimed before `run_after`.
+    pub async fn schedule_queue(
+        &mut self,
+        event: String,
+        data: String,
+        run_after: SystemTime,
+    ) -> Result<i32, AnyError> {
+        let mut job = QueueJob::new(event, data);
+        job.run_after = Some(run_after);
+
+        Ok(self.enqueue(job).await?.id)
+    }
+
+    /// Counts the queue items by event and state.
+    pub async fn queue_stats(&mut self) -> Result<Vec<QueueStats>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .queue_stats
+            .bind(&self.connection.connection, &[])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            out.push(QueueStats::make_from_row(&item?));
+        }
+
+        Ok(out)
+    }
+
+    /// Lists the oldest queue items, optionally only those with a specific event or state.
+    pub async fn peek_queue(
+        &mut self,
+        event: Option<String>,
+        state: Option<String>,
+        limit: u32,
+    ) -> Result<Vec<QueueItemInfo>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .queue_peek
+            .bind(
+                &self.connection.connection,
+                &[&event, &state, &(limit as i64)],
+            )
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            out.push(QueueItemInfo::make_from_row(&item?));
+        }
+
+        Ok(out)
+    }
+
+    /// Moves dead-lettered queue items back to the pending state, with their attempts reset.
+    /// Workers waiting on `QUEUE_CHANNEL` are notified. Returns the amount of items requeued.
+    pub async fn requeue_dead(&mut self, event: Option<String>) -> Result<u64, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .queue_requeue
+            .bind(&self.connection.connection, &[&event])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut count = 0;
+        while let Some(item) = query.next().await {
+            item?;
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Removes all the queue items with a specific event, group, or both.
+    /// Returns the amount of items removed.
+    pub async fn purge_queue(
+        &mut self,
+        event: Option<String>,
+        group: Option<String>,
+    ) -> Result<u64, AnyError> {
+        if event.is_none() && group.is_none() {
+            return Err("refusing to purge the entire queue".into());
+        }
+
+        let mut bound = self
+            .connection
+            .statements
+            .queue_purge
+            .bind(&self.connection.connection, &[&event, &group])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut count = 0;
+        while let Some(item) = query.next().await {
+            item?;
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Stops queue items with a specific event from being claimed, until resumed.
+    pub async fn pause_queue(&mut self, event: String) -> Result<(), AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .queue_pause
+            .bind(&self.connection.connection, &[&event])
             .await?;
         let mut query = bound.execute(&self.connection.connection).await?;
 
@@ -331,4 +1095,137 @@ impl<'a> CellarEntityStore<'a> {
 
         Ok(())
     }
+
+    /// Allows queue items with a specific event to be claimed again.
+    pub async fn resume_queue(&mut self, event: String) -> Result<(), AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .queue_resume
+            .bind(&self.connection.connection, &[&event])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        while let Some(item) = query.next().await {
+            item?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists the events that are currently paused.
+    pub async fn paused_queues(&mut self) -> Result<Vec<String>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .queue_paused
+            .bind(&self.connection.connection, &[])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            out.push(item?.get(0)?.unwrap());
+        }
+
+        Ok(out)
+    }
+
+    /// Creates or replaces a recurring job, that is first added to the queue at its next run.
+    pub async fn set_schedule(
+        &mut self,
+        name: String,
+        event: String,
+        data: String,
+        schedule: Schedule,
+    ) -> Result<(), AnyError> {
+        let now = SystemTime::now();
+        let next_run = time_to_epoch(schedule.next(now, now));
+        let (interval, cron) = match schedule {
+            Schedule::Interval(interval) => (Some(interval.as_secs_f64()), None),
+            Schedule::Cron(cron) => (None, Some(cron.source().to_owned())),
+        };
+
+        let mut bound = self
+            .connection
+            .statements
+            .schedule_put
+            .bind(
+                &self.connection.connection,
+                &[&name, &event, &data, &interval, &cron, &next_run],
+            )
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        while let Some(item) = query.next().await {
+            item?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a recurring job. Items it already added to the queue are kept.
+    pub async fn remove_schedule(&mut self, name: String) -> Result<(), AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .schedule_delete
+            .bind(&self.connection.connection, &[&name])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        while let Some(item) = query.next().await {
+            item?;
+        }
+
+        Ok(())
+    }
+
+    /// Lists the recurring jobs, or only those that are due if `due` is set.
+    pub async fn select_schedules(&mut self, due: bool) -> Result<Vec<RecurringJob>, AnyError> {
+        let mut bound = self
+            .connection
+            .statements
+            .schedule_select
+            .bind(&self.connection.connection, &[&due])
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut out = Vec::new();
+        while let Some(item) = query.next().await {
+            out.push(RecurringJob::make_from_row(&item?)?);
+        }
+
+        Ok(out)
+    }
+
+    /// Adds a recurring job to the queue and moves it to its next run, if it is still at `tick`.
+    /// Returns the ID of the new queue item, or `None` if another scheduler got there first.
+    pub async fn fire_schedule(
+        &mut self,
+        name: &str,
+        tick: i64,
+        next_run: SystemTime,
+    ) -> Result<Option<i32>, AnyError> {
+        let next_run = time_to_epoch(next_run);
+        let mut bound = self
+            .connection
+            .statements
+            .schedule_fire
+            .bind(
+                &self.connection.connection,
+                &[&name.to_owned(), &tick, &next_run],
+            )
+            .await?;
+        let mut query = bound.execute(&self.connection.connection).await?;
+
+        let mut id = None;
+        while let Some(item) = query.next().await {
+            let item = item?;
+
+            id = item.get(0)?;
+        }
+
+        Ok(id)
+    }
 }
@@ -0,0 +1,329 @@
+use postgres_async::types::AnyError;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often a recurring queue job is added to the queue.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Every fixed amount of time.
+    Interval(Duration),
+
+    /// Every time a cron expression matches.
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    /// Calculates the next run of a job, after it was due at `previous`. Runs missed while no
+    /// scheduler was running are skipped, instead of being run in a burst.
+    pub fn next(&self, previous: SystemTime, now: SystemTime) -> SystemTime {
+        match self {
+            Schedule::Interval(interval) => {
+                let next = previous + *interval;
+                if next <= now {
+                    now + *interval
+                } else {
+                    next
+                }
+            }
+
+            Schedule::Cron(cron) => cron
+                .next_after(now)
+                .unwrap_or_else(|| now + Duration::from_secs(24 * 60 * 60)),
+        }
+    }
+}
+
+/// A parsed five-field cron expression (minute, hour, day of month, month, day of week),
+/// evaluated in UTC. Fields support `*`, numbers, ranges (`a-b`), steps (`*/n`, `a-b/n`) and
+/// comma-separated lists of these.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+
+    // if either day field starts with `*`, both have to match. otherwise either one has to.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<(u64, bool), AnyError> {
+    let mut bits = 0u64;
+
+    for part in field.split(',') {
+        let mut pieces = part.splitn(2, '/');
+        let range = pieces.next().unwrap();
+        let step = match pieces.next() {
+            Some(step) => step.parse::<u32>()?,
+            None => 1,
+        };
+
+        if step == 0 {
+            return Err(format!("invalid step in cron field {}", field).into());
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(index) = range.find('-') {
+            (range[..index].parse()?, range[index + 1..].parse()?)
+        } else {
+            let value = range.parse()?;
+            // `a/n` means every n-th value starting at a
+            (value, if step == 1 { value } else { max })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("cron field {} out of range {}-{}", field, min, max).into());
+        }
+
+        let mut value = start;
+        while value <= end {
+            bits |= 1 << value;
+            value += step;
+        }
+    }
+
+    // like in vixie cron, `*/n` counts as unrestricted for the day-of-month/day-of-week rule
+    Ok((bits, field.starts_with('*')))
+}
+
+/// Converts days since the UNIX epoch into a (month, day) pair, in the proleptic Gregorian calendar.
+fn month_and_day(days: i64) -> (u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+    (month as u32, day as u32)
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<CronSchedule, AnyError> {
+        let fields: Vec<_> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("cron expression {} does not have 5 fields", expression).into());
+        }
+
+        let (minutes, _) = parse_field(fields[0], 0, 59)?;
+        let (hours, _) = parse_field(fields[1], 0, 23)?;
+        let (days, any_day) = parse_field(fields[2], 1, 31)?;
+        let (months, _) = parse_field(fields[3], 1, 12)?;
+        let (mut weekdays, any_weekday) = parse_field(fields[4], 0, 7)?;
+
+        // both 0 and 7 are sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        let schedule = CronSchedule {
+            source: expression.to_owned(),
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            any_day,
+            any_weekday,
+        };
+
+        if schedule.next_after(SystemTime::now()).is_none() {
+            return Err(format!("cron expression {} never matches", expression).into());
+        }
+
+        Ok(schedule)
+    }
+
+    /// The expression this schedule was parsed from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn day_matches(&self, days: i64) -> bool {
+        let (month, day) = month_and_day(days);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+
+        // the UNIX epoch was a thursday
+        let weekday = (days + 4).rem_euclid(7);
+        let day_match = self.days & (1 << day) != 0;
+        let weekday_match = self.weekdays & (1 << weekday) != 0;
+
+        if self.any_day || self.any_weekday {
+            day_match && weekday_match
+        } else {
+            day_match || weekday_match
+        }
+    }
+
+    /// Finds the first minute strictly after `time` that matches, looking at most 5 years ahead.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let mut minute = time.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64 / 60 + 1;
+        let limit = minute + 5 * 366 * 24 * 60;
+
+        while minute < limit {
+            let days = minute / (24 * 60);
+            if !self.day_matches(days) {
+                minute = (days + 1) * 24 * 60;
+                continue;
+            }
+
+            if self.hours & (1 << (minute / 60 % 24)) == 0 {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+
+            if self.minutes & (1 << (minute % 60)) == 0 {
+                minute += 1;
+                continue;
+            }
+
+            return Some(UNIX_EPOCH + Duration::from_secs(minute as u64 * 60));
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Converts a UTC date and time into a `SystemTime`.
+    fn at(year: i64, month: i64, day: i64, hour: u64, minute: u64) -> SystemTime {
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = if y >= 0 { y } else { y - 399 } / 400;
+        let yoe = y - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = (era * 146_097 + doe - 719_468) as u64;
+
+        UNIX_EPOCH + Duration::from_secs(days * 24 * 60 * 60 + hour * 60 * 60 + minute * 60)
+    }
+
+    fn next(expression: &str, time: SystemTime) -> SystemTime {
+        CronSchedule::parse(expression)
+            .unwrap()
+            .next_after(time)
+            .unwrap()
+    }
+
+    #[test]
+    fn next_is_strictly_after() {
+        let time = at(2021, 3, 4, 10, 30);
+        assert_eq!(next("* * * * *", time), at(2021, 3, 4, 10, 31));
+        assert_eq!(next("30 10 * * *", time), at(2021, 3, 5, 10, 30));
+    }
+
+    #[test]
+    fn ranges_steps_and_lists() {
+        let time = at(2021, 3, 4, 10, 30);
+        assert_eq!(next("0-5 11 * * *", time), at(2021, 3, 4, 11, 0));
+        assert_eq!(next("*/20 * * * *", time), at(2021, 3, 4, 10, 40));
+        assert_eq!(next("10-50/15 * * * *", time), at(2021, 3, 4, 10, 40));
+        assert_eq!(next("5/30 * * * *", time), at(2021, 3, 4, 10, 35));
+        assert_eq!(next("0 3,9,15 * * *", time), at(2021, 3, 4, 15, 0));
+        assert_eq!(next("0 1-2,20/2 * * *", time), at(2021, 3, 4, 20, 0));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("* * 0 * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("5-1 * * * *").is_err());
+        assert!(CronSchedule::parse("0 0 31 2 *").is_err());
+    }
+
+    #[test]
+    fn month_end() {
+        assert_eq!(
+            next("0 0 31 * *", at(2021, 4, 1, 0, 0)),
+            at(2021, 5, 31, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 1 * *", at(2021, 12, 31, 12, 0)),
+            at(2022, 1, 1, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 30 * *", at(2021, 1, 30, 0, 0)),
+            at(2021, 3, 30, 0, 0)
+        );
+    }
+
+    #[test]
+    fn leap_years() {
+        assert_eq!(
+            next("0 0 29 2 *", at(2021, 3, 1, 0, 0)),
+            at(2024, 2, 29, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 29 2 *", at(2099, 3, 1, 0, 0)),
+            at(2104, 2, 29, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 1 3 *", at(2000, 2, 28, 0, 0)),
+            at(2000, 3, 1, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 29 2 *", at(1999, 3, 1, 0, 0)),
+            at(2000, 2, 29, 0, 0)
+        );
+    }
+
+    #[test]
+    fn restricted_days_match_either_field() {
+        // 2021-03-04 is a thursday; the 13th is on a saturday
+        let time = at(2021, 3, 4, 12, 0);
+        assert_eq!(next("0 0 13 * 5", time), at(2021, 3, 5, 0, 0));
+        assert_eq!(next("0 0 5 * 1", time), at(2021, 3, 5, 0, 0));
+        assert_eq!(next("0 0 13 * 0", time), at(2021, 3, 7, 0, 0));
+    }
+
+    #[test]
+    fn unrestricted_days_match_both_fields() {
+        let time = at(2021, 3, 4, 12, 0);
+        assert_eq!(next("0 0 * * 1", time), at(2021, 3, 8, 0, 0));
+        assert_eq!(next("0 0 13 * *", time), at(2021, 3, 13, 0, 0));
+
+        // a step on `*` still counts as unrestricted
+        assert_eq!(next("0 0 */2 * 1", time), at(2021, 3, 15, 0, 0));
+        assert_eq!(next("0 0 13 * */7", time), at(2021, 6, 13, 0, 0));
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        let time = at(2021, 3, 4, 12, 0);
+        assert_eq!(next("0 0 * * 7", time), at(2021, 3, 7, 0, 0));
+        assert_eq!(next("0 0 * * 0", time), at(2021, 3, 7, 0, 0));
+    }
+
+    #[test]
+    fn interval_skips_missed_runs() {
+        let schedule = Schedule::Interval(Duration::from_secs(60));
+        let previous = at(2021, 3, 4, 10, 0);
+        assert_eq!(
+            schedule.next(previous, at(2021, 3, 4, 10, 0)),
+            at(2021, 3, 4, 10, 1)
+        );
+        assert_eq!(
+            schedule.next(previous, at(2021, 3, 4, 11, 0)),
+            at(2021, 3, 4, 11, 1)
+        );
+    }
+}
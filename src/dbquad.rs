@@ -3,6 +3,7 @@ use std::collections::HashSet;
 use postgres_async::types::Row;
 
 /// The contents of a single database quad.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum DatabaseQuadContents {
     /// This quad points to another subject.
     Id(i32),
@@ -14,6 +15,29 @@ pub enum DatabaseQuadContents {
     LanguageString { contents: String, language: String },
 }
 
+impl DatabaseQuadContents {
+    /// Interprets the `attribute_id`, `object`, `type_id` and `language` columns of a quad.
+    pub fn from_columns(
+        attribute_id: Option<i32>,
+        object: Option<String>,
+        type_id: Option<i32>,
+        language: Option<String>,
+    ) -> Option<DatabaseQuadContents> {
+        match (attribute_id, object, type_id, language) {
+            (Some(id), _, _, _) => Some(DatabaseQuadContents::Id(id)),
+            (_, Some(contents), _, Some(language)) => Some(DatabaseQuadContents::LanguageString {
+                contents: contents,
+                language: language,
+            }),
+            (_, Some(contents), Some(type_id), _) => Some(DatabaseQuadContents::Object {
+                contents: contents,
+                type_id: type_id,
+            }),
+            _ => None,
+        }
+    }
+}
+
 /// The raw contents of a single database quad.
 pub struct DatabaseQuad {
     pub id: i32,
@@ -25,23 +49,13 @@ pub struct DatabaseQuad {
 
 impl DatabaseQuad {
     pub fn make_from_row(row: &Row) -> DatabaseQuad {
-        let contents = match (
+        let contents = DatabaseQuadContents::from_columns(
             row.get(4).unwrap(),
             row.get(5).unwrap(),
             row.get(6).unwrap(),
             row.get(7).unwrap(),
-        ) {
-            (Some(id), _, _, _) => DatabaseQuadContents::Id(id),
-            (_, Some(contents), _, Some(language)) => DatabaseQuadContents::LanguageString {
-                contents: contents,
-                language: language,
-            },
-            (_, Some(contents), Some(type_id), _) => DatabaseQuadContents::Object {
-                contents: contents,
-                type_id: type_id,
-            },
-            _ => panic!("invalid quad contents; impossible"),
-        };
+        )
+        .expect("invalid quad contents; impossible");
 
         DatabaseQuad {
             id: row.get(0).unwrap().unwrap(),
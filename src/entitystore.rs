@@ -1,11 +1,18 @@
-use crate::CellarEntityStore;
+use crate::dbquad::DatabaseQuadContents;
+use crate::query::{build_query, query_uris};
+use crate::types::{CollectionItem, OrderedKey};
+use crate::{CellarEntityStore, CellarQuery, QueryPage, VersionConflict};
 use jsonld::rdf::{jsonld_to_rdf, rdf_to_jsonld, QuadContents, StringQuad};
 use kroeg_tap::StoreItemNodeGenerator;
-use kroeg_tap::{
-    CollectionPointer, EntityStore, QuadQuery, QueryId, QueryObject, StoreError, StoreItem,
-};
+use kroeg_tap::{CollectionPointer, EntityStore, QuadQuery, StoreError, StoreItem};
 use serde_json::Value as JValue;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::time::SystemTime;
+
+/// The amount of times `update_entity` tries to write an entity that is being changed
+/// concurrently, before returning the conflict.
+pub const MAX_WRITE_ATTEMPTS: u32 = 5;
 
 fn get_ids(quad: &StringQuad, set: &mut HashSet<String>) {
     match &quad.contents {
@@ -17,12 +24,91 @@ fn get_ids(quad: &StringQuad, set: &mut HashSet<String>) {
     set.insert(quad.predicate_id.to_owned());
 }
 
-#[async_trait::async_trait]
-/// An entity store, storing JSON-LD `Entity` objects.
-impl<'a> EntityStore for CellarEntityStore<'a> {
-    /// Gets a single `StoreItem` from the store. Missing entities are no error,
-    /// but instead returns a `None`.
-    async fn get(&mut self, path: String, _local: bool) -> Result<Option<StoreItem>, StoreError> {
+/// Parses a `before-` or `after-` collection cursor into the direction to read in (`true` for
+/// older items) and the row position to start at.
+fn parse_cursor(cursor: Option<String>) -> Result<(bool, i32), StoreError> {
+    Ok(match cursor {
+        None => (true, i32::max_value()),
+        Some(ref value) if value.starts_with("before-") => (false, value[7..].parse::<i32>()?),
+        Some(ref value) if value.starts_with("after-") => (true, value[6..].parse::<i32>()?),
+        _ => return Err("unknown collection cursor".into()),
+    })
+}
+
+/// Makes the `after` and `before` cursors of a page of collection rows, read from `offset`.
+fn page_cursors(
+    items: &[CollectionItem],
+    until: bool,
+    offset: i32,
+) -> (Option<String>, Option<String>) {
+    let after = items
+        .iter()
+        .last()
+        .and_then(|f| f.id.checked_sub(1))
+        .or(if !until { offset.checked_sub(1) } else { None })
+        .map(|var| format!("after-{}", var));
+    let before = items
+        .iter()
+        .next()
+        .and_then(|f| f.id.checked_add(1))
+        .or(if until { offset.checked_add(1) } else { None })
+        .map(|var| format!("before-{}", var));
+
+    (after, before)
+}
+
+/// Converts a stored count into the count of a `CollectionPointer`, which is unknown if it does
+/// not fit.
+fn pointer_count(total: i64) -> Option<u32> {
+    u32::try_from(total).ok()
+}
+
+/// Parses the `{added_at}:{row}` cursor of `read_collection_range`.
+fn parse_range_cursor(cursor: &str) -> Result<(i64, i32), StoreError> {
+    let index = cursor.find(':').ok_or("unknown collection cursor")?;
+
+    Ok((
+        cursor[..index].parse::<i64>()?,
+        cursor[index + 1..].parse::<i32>()?,
+    ))
+}
+
+/// Parses the `{row}:{class}:{number}:{value}` key of an ordered collection cursor.
+fn parse_ordered_key(key: &str) -> Result<OrderedKey, StoreError> {
+    let mut parts = key.splitn(4, ':');
+    let mut next = || parts.next().ok_or("unknown collection cursor");
+
+    Ok(OrderedKey {
+        id: next()?.parse::<i32>()?,
+        class: next()?.parse::<i32>()?,
+        number: next()?.to_owned(),
+        value: next()?.to_owned(),
+    })
+}
+
+/// Formats a key of an ordered collection cursor, the inverse of `parse_ordered_key`.
+fn format_ordered_key(key: &OrderedKey) -> String {
+    format!("{}:{}:{}:{}", key.id, key.class, key.number, key.value)
+}
+
+impl<'a> CellarEntityStore<'a> {
+    /// Gets a single `StoreItem` from the store, together with its version. The version is
+    /// read before the quads, so it is never newer than the returned item.
+    pub async fn get_versioned(
+        &mut self,
+        path: String,
+    ) -> Result<Option<(StoreItem, i64)>, StoreError> {
+        self.cache_uris(&[path.clone()]).await?;
+
+        // the object cache may lag behind the version, so this always reads the database.
+        let version = self.read_version(self.cache.uri_to_id[&path]).await?;
+        let item = self.read_entity(path).await?;
+
+        Ok(item.map(|item| (item, version)))
+    }
+
+    /// Reads a single `StoreItem` from the database, bypassing the object cache, and caches it.
+    pub async fn read_entity(&mut self, path: String) -> Result<Option<StoreItem>, StoreError> {
         let id = path.to_owned();
 
         self.cache_uris(&[path.clone()]).await?;
@@ -30,6 +116,8 @@ impl<'a> EntityStore for CellarEntityStore<'a> {
         let quads = self.read_quad(self.cache.uri_to_id[&path]).await?;
         let translated = self.translate_quads(quads).await?;
         if translated.is_empty() {
+            self.invalidate(&path);
+
             return Ok(None);
         }
 
@@ -38,286 +126,599 @@ impl<'a> EntityStore for CellarEntityStore<'a> {
 
         if let JValue::Object(jval) = rdf_to_jsonld(&hash, true, false) {
             let jval = JValue::Array(jval.into_iter().map(|(_, b)| b).collect());
-            Ok(Some(StoreItem::parse(&id, &jval)?))
+            let item = StoreItem::parse(&id, &jval)?;
+            self.cache_object(id, item.clone());
+
+            Ok(Some(item))
         } else {
             unreachable!();
         }
     }
 
-    /// Stores a single `StoreItem` into the store.
-    ///
-    /// To delete an Entity, set its type to as:Tombstone. This may
-    /// instantly remove it, or queue it for possible future deletion.
-    async fn put(&mut self, path: String, item: &mut StoreItem) -> Result<(), StoreError> {
-        let rdf = item.clone().to_json();
+    /// Stores a single `StoreItem` into the store, only touching the quads that changed.
+    /// All deletions and insertions happen atomically. Returns whether anything changed.
+    /// If the entity keeps being changed concurrently, this gives up after
+    /// `MAX_WRITE_ATTEMPTS` and returns the `VersionConflict`.
+    pub async fn update_entity(
+        &mut self,
+        path: String,
+        item: &mut StoreItem,
+    ) -> Result<bool, StoreError> {
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            // a conflict here means someone else wrote between reading and writing the quads,
+            // so the diff has to be recomputed.
+            match self.write_entity(&path, item, None).await {
+                Err(ref e) if e.is::<VersionConflict>() && attempts < MAX_WRITE_ATTEMPTS => {
+                    continue
+                }
+                result => return result.map(|(changed, _)| changed),
+            }
+        }
+    }
 
-        let mut rdf = jsonld_to_rdf(&rdf, &mut StoreItemNodeGenerator::new()).unwrap();
-        let mut set = HashSet::new();
+    /// Stores a single `StoreItem` into the store, if the stored version is still `expected`.
+    /// Returns the version after the write. Fails with a `VersionConflict` if the version has
+    /// moved on. Entities that do not exist yet are at version 0.
+    pub async fn put_if_version(
+        &mut self,
+        path: String,
+        item: &mut StoreItem,
+        expected: i64,
+    ) -> Result<i64, StoreError> {
+        let (_, version) = self.write_entity(&path, item, Some(expected)).await?;
 
-        let quads = rdf.remove("@default").unwrap();
-        for quad in &quads {
-            get_ids(quad, &mut set);
-        }
+        Ok(version)
+    }
 
-        set.insert(path.to_owned());
+    /// Finds the collections containing a specific object, paginated like `read_collection`.
+    /// If `prefix` is set, only collections whose URI starts with it are returned.
+    pub async fn read_collection_inverse_page(
+        &mut self,
+        item: String,
+        count: Option<u32>,
+        cursor: Option<String>,
+        prefix: Option<String>,
+    ) -> Result<CollectionPointer, StoreError> {
+        let (until, offset) = parse_cursor(cursor)?;
 
-        let set: Vec<_> = set.into_iter().collect();
-        self.cache_uris(&set).await?;
+        self.cache_uris(&[item.to_owned()]).await?;
+        let id = self.cache.uri_to_id[&item];
+        let items = self
+            .select_collection_inverse(id, offset, count.unwrap_or(30) as i32, until, &prefix)
+            .await?;
+        let total = self.collection_inverse_count(id, &prefix).await?;
 
-        let mut quad_id = Vec::with_capacity(quads.len());
-        let mut subject_id = Vec::with_capacity(quads.len());
-        let mut predicate_id = Vec::with_capacity(quads.len());
-        let mut attribute_id = Vec::with_capacity(quads.len());
-        let mut object = Vec::with_capacity(quads.len());
-        let mut type_id = Vec::with_capacity(quads.len());
-        let mut language = Vec::with_capacity(quads.len());
+        let ids: Vec<_> = items.iter().map(|f| f.collection_id).collect();
 
-        let qid = self.cache.uri_to_id[&path];
+        self.cache_ids(&ids).await?;
 
-        for quad in quads {
-            quad_id.push(qid);
-            subject_id.push(self.cache.uri_to_id[&quad.subject_id]);
-            predicate_id.push(self.cache.uri_to_id[&quad.predicate_id]);
-
-            match quad.contents {
-                QuadContents::Id(id) => {
-                    attribute_id.push(Some(self.cache.uri_to_id[&id]));
-                    object.push(None);
-                    type_id.push(None);
-                    language.push(None);
-                }
+        let (after, before) = page_cursors(&items, until, offset);
+        Ok(CollectionPointer {
+            items: items
+                .iter()
+                .map(|f| self.cache.id_to_uri[&f.collection_id].to_owned())
+                .collect(),
+            after,
+            before,
+            count: pointer_count(total),
+        })
+    }
 
-                QuadContents::Object(typ_id, content, languag) => {
-                    attribute_id.push(None);
-                    object.push(Some(content));
-                    type_id.push(Some(self.cache.uri_to_id[&typ_id]));
-                    language.push(languag);
-                }
+    /// Reads up to `count` items of a collection that were added in a time range, newest first,
+    /// together with the time they were added. `since` is inclusive and `until` exclusive.
+    /// Also returns a cursor to pass to read the next page, or `None` if this was the last one.
+    pub async fn read_collection_range(
+        &mut self,
+        path: String,
+        since: Option<SystemTime>,
+        until: Option<SystemTime>,
+        cursor: Option<String>,
+        count: u32,
+    ) -> Result<(Vec<(String, SystemTime)>, Option<String>), StoreError> {
+        let after = cursor.as_deref().map(parse_range_cursor).transpose()?;
+        let limit = i32::try_from(count).unwrap_or(i32::max_value());
+
+        self.cache_uris(&[path.to_owned()]).await?;
+        let items = self
+            .select_collection_range(self.cache.uri_to_id[&path], since, until, after, limit)
+            .await?;
+
+        let ids: Vec<_> = items.iter().map(|(f, _)| f.object_id).collect();
+        self.cache_ids(&ids).await?;
+
+        let next = match items.last() {
+            Some((_, (time, id))) if items.len() == limit as usize => {
+                Some(format!("{}:{}", time, id))
             }
-        }
+            _ => None,
+        };
 
-        self.delete_quad(qid).await?;
-        self.insert_quad(&[
-            &quad_id,
-            &subject_id,
-            &predicate_id,
-            &attribute_id,
-            &object,
-            &type_id,
-            &language,
-        ])
-        .await?;
+        let items = items
+            .into_iter()
+            .map(|(f, _)| (self.cache.id_to_uri[&f.object_id].to_owned(), f.added_at))
+            .collect();
 
-        Ok(())
+        Ok((items, next))
     }
 
-    /// Queries the entire store for a specific set of parameters.
-    /// The return value is a list for every result in the database that matches the query.
-    /// The array elements are in numeric order of the placeholders.
-    async fn query(&mut self, query: Vec<QuadQuery>) -> Result<Vec<Vec<String>>, StoreError> {
-        // stores a map of statements (e.g. quad_1.quad_id) and what they should be equal to. (e.g. quad_1.quad_id -> [quad_2.attribute_id])
-        let mut placeholders = BTreeMap::new();
+    /// Reads the items of a collection that are also in `other`, paginated like
+    /// `read_collection`. The pointer has no count.
+    pub async fn read_collection_intersection(
+        &mut self,
+        path: String,
+        other: String,
+        count: Option<u32>,
+        cursor: Option<String>,
+    ) -> Result<CollectionPointer, StoreError> {
+        self.read_collection_compare(path, other, count, cursor, false)
+            .await
+    }
 
-        // stores a map of attribute value and the IDs they should be equal to (e.g. quad_1.predicate_id -> https://www.w3.org/ns/activitystreams#object)
-        let mut checks = HashMap::new();
+    /// Reads the items of a collection that are not in `other`, paginated like
+    /// `read_collection`. The pointer has no count.
+    pub async fn read_collection_difference(
+        &mut self,
+        path: String,
+        other: String,
+        count: Option<u32>,
+        cursor: Option<String>,
+    ) -> Result<CollectionPointer, StoreError> {
+        self.read_collection_compare(path, other, count, cursor, true)
+            .await
+    }
 
-        // same as checks, but for being equal to any of a list.
-        let mut checks_any = HashMap::new();
+    /// Reads several collections merged into one, e.g. the outboxes of every followed actor for
+    /// a home timeline. Items are ordered by when they were inserted into their collection, and
+    /// paginated like `read_collection`. An item that is in more than one of the collections is
+    /// returned once for every collection. The pointer has no count.
+    pub async fn read_collection_merged(
+        &mut self,
+        paths: Vec<String>,
+        count: Option<u32>,
+        cursor: Option<String>,
+    ) -> Result<CollectionPointer, StoreError> {
+        let (until, offset) = parse_cursor(cursor)?;
 
-        // stores statement -> string value comparisons, for contents/language tests
-        let mut others = Vec::new();
+        self.cache_uris(&paths).await?;
+        let collections: Vec<_> = paths.iter().map(|f| self.cache.uri_to_id[f]).collect();
+        let items = self
+            .select_collection_merged(collections, offset, count.unwrap_or(30) as i32, until)
+            .await?;
 
-        let quad_count = query.len();
+        let ids: Vec<_> = items.iter().map(|f| f.object_id).collect();
+        self.cache_ids(&ids).await?;
 
-        for (i, QuadQuery(subject, predicate, object)) in query.into_iter().enumerate() {
-            match subject {
-                QueryId::Value(val) => {
-                    checks.insert(format!("quad_{}.quad_id", i), val);
-                }
-                QueryId::Placeholder(val) => {
-                    if !placeholders.contains_key(&val) {
-                        placeholders.insert(val, vec![format!("quad_{}.quad_id", i)]);
-                    } else {
-                        placeholders
-                            .get_mut(&val)
-                            .unwrap()
-                            .push(format!("quad_{}.quad_id", i));
-                    }
-                }
-                QueryId::Any(any) => {
-                    if any.len() == 0 {
-                        return Ok(vec![]);
-                    }
+        let (after, before) = page_cursors(&items, until, offset);
+        Ok(CollectionPointer {
+            items: items
+                .iter()
+                .map(|f| self.cache.id_to_uri[&f.object_id].to_owned())
+                .collect(),
+            after,
+            before,
+            count: None,
+        })
+    }
 
-                    checks_any.insert(format!("quad_{}.quad_id", i), any);
-                }
-                QueryId::Ignore => {}
+    /// Reads a collection ordered by the value of `predicate` on its items, e.g. `as:published`,
+    /// instead of by when they were inserted. Values typed as an XSD number or `xsd:dateTime`
+    /// are compared as numbers and points in time, and other values as strings. The cursors
+    /// hold the value and row of the item at the edge of the page, so they stay valid when items
+    /// are inserted or removed.
+    pub async fn read_collection_ordered(
+        &mut self,
+        path: String,
+        predicate: String,
+        count: Option<u32>,
+        cursor: Option<String>,
+    ) -> Result<CollectionPointer, StoreError> {
+        let (until, key) = match cursor {
+            None => (true, None),
+            Some(ref value) if value.starts_with("before-") => {
+                (false, Some(parse_ordered_key(&value[7..])?))
             }
-            match predicate {
-                QueryId::Value(val) => {
-                    checks.insert(format!("quad_{}.predicate_id", i), val);
-                }
-                QueryId::Placeholder(val) => {
-                    if !placeholders.contains_key(&val) {
-                        placeholders.insert(val, vec![format!("quad_{}.predicate_id", i)]);
-                    } else {
-                        placeholders
-                            .get_mut(&val)
-                            .unwrap()
-                            .push(format!("quad_{}.predicate_id", i));
-                    }
-                }
-                QueryId::Any(any) => {
-                    if any.len() == 0 {
-                        return Ok(vec![]);
-                    }
+            Some(ref value) if value.starts_with("after-") => {
+                (true, Some(parse_ordered_key(&value[6..])?))
+            }
+            _ => return Err("unknown collection cursor".into()),
+        };
+
+        self.cache_uris(&[path.to_owned(), predicate.to_owned()])
+            .await?;
+        let collection = self.cache.uri_to_id[&path];
+        let items = self
+            .select_collection_ordered(
+                collection,
+                self.cache.uri_to_id[&predicate],
+                key.clone(),
+                count.unwrap_or(30) as i32,
+                until,
+            )
+            .await?;
+        let total = self.collection_count(collection).await?;
+
+        let ids: Vec<_> = items.iter().map(|(f, _)| f.object_id).collect();
+        self.cache_ids(&ids).await?;
+
+        let edge = |(_, key): &(CollectionItem, OrderedKey)| key.clone();
+        let after = items
+            .last()
+            .map(edge)
+            .or(if !until { key.clone() } else { None })
+            .map(|key| format!("after-{}", format_ordered_key(&key)));
+        let before = items
+            .first()
+            .map(edge)
+            .or(if until { key } else { None })
+            .map(|key| format!("before-{}", format_ordered_key(&key)));
+
+        Ok(CollectionPointer {
+            items: items
+                .iter()
+                .map(|(f, _)| self.cache.id_to_uri[&f.object_id].to_owned())
+                .collect(),
+            after,
+            before,
+            count: pointer_count(total),
+        })
+    }
+
+    /// Counts the distinct items that are in any of the collections.
+    pub async fn read_collection_union_count(
+        &mut self,
+        paths: Vec<String>,
+    ) -> Result<u64, StoreError> {
+        self.cache_uris(&paths).await?;
+        let ids: Vec<_> = paths.iter().map(|f| self.cache.uri_to_id[f]).collect();
+
+        Ok(u64::try_from(self.collection_union_count(ids).await?)?)
+    }
+
+    /// Returns which of the items are in the collection, in the order they were passed in.
+    pub async fn read_collection_members(
+        &mut self,
+        path: String,
+        items: Vec<String>,
+    ) -> Result<Vec<String>, StoreError> {
+        let mut uris = items.clone();
+        uris.push(path.to_owned());
+        self.cache_uris(&uris).await?;
+
+        let ids: Vec<_> = items.iter().map(|f| self.cache.uri_to_id[f]).collect();
+        let found: HashSet<_> = self
+            .select_collection_members(self.cache.uri_to_id[&path], ids)
+            .await?
+            .into_iter()
+            .collect();
+
+        Ok(items
+            .into_iter()
+            .filter(|f| found.contains(&self.cache.uri_to_id[f]))
+            .collect())
+    }
+
+    async fn read_collection_compare(
+        &mut self,
+        path: String,
+        other: String,
+        count: Option<u32>,
+        cursor: Option<String>,
+        difference: bool,
+    ) -> Result<CollectionPointer, StoreError> {
+        let (until, offset) = parse_cursor(cursor)?;
+
+        self.cache_uris(&[path.to_owned(), other.to_owned()])
+            .await?;
+        let collection = self.cache.uri_to_id[&path];
+        let other = self.cache.uri_to_id[&other];
+        let items = self
+            .select_collection_intersect(
+                collection,
+                other,
+                offset,
+                count.unwrap_or(30) as i32,
+                until,
+                difference,
+            )
+            .await?;
+
+        let ids: Vec<_> = items.iter().map(|f| f.object_id).collect();
+        self.cache_ids(&ids).await?;
+
+        let (after, before) = page_cursors(&items, until, offset);
+        Ok(CollectionPointer {
+            items: items
+                .iter()
+                .map(|f| self.cache.id_to_uri[&f.object_id].to_owned())
+                .collect(),
+            after,
+            before,
+            count: None,
+        })
+    }
+
+    /// Queries the store like `EntityStore::query`, but only returns the page of results asked
+    /// for. Every value is bound as a parameter, and statements are reused per shape of query.
+    pub async fn query_page(&mut self, query: CellarQuery) -> Result<QueryPage, StoreError> {
+        self.cache_uris(&query_uris(&query)).await?;
+
+        let built = build_query(&query, &self.cache.uri_to_id)?;
+        let params: Vec<_> = built.params.iter().map(|f| f.as_ref()).collect();
+        let result = self.do_query(built.sql, &params).await?;
 
-                    checks_any.insert(format!("quad_{}.predicate_id", i), any);
+        let mut ids = Vec::new();
+        for row in &result {
+            for (i, literal) in built.literal.iter().enumerate() {
+                if !literal {
+                    ids.extend(row.get::<i32>(i)?);
                 }
-                QueryId::Ignore => {}
             }
+        }
 
-            match object {
-                QueryObject::Id(QueryId::Value(val)) => {
-                    checks.insert(format!("quad_{}.attribute_id", i), val);
-                }
-                QueryObject::Id(QueryId::Placeholder(val)) => {
-                    if !placeholders.contains_key(&val) {
-                        placeholders.insert(val, vec![format!("quad_{}.attribute_id", i)]);
-                    } else {
-                        placeholders
-                            .get_mut(&val)
-                            .unwrap()
-                            .push(format!("quad_{}.attribute_id", i));
-                    }
+        self.cache_ids(&ids).await?;
+
+        let mut data = vec![];
+        for row in &result {
+            let mut row_out = Vec::with_capacity(built.placeholders.len());
+            for (i, literal) in built.literal.iter().enumerate() {
+                if *literal {
+                    row_out.push(row.get::<String>(i)?);
+                } else {
+                    let id = row.get::<i32>(i)?;
+                    row_out.push(id.map(|f| self.cache.id_to_uri[&f].to_owned()));
                 }
+            }
 
-                QueryObject::Id(QueryId::Any(any)) => {
-                    if any.len() == 0 {
-                        return Ok(vec![]);
-                    }
+            data.push(row_out);
+        }
 
-                    checks_any.insert(format!("quad_{}.attribute_id", i), any);
-                }
+        // the cursor holds the values the last row is ordered by
+        let mut cursor = None;
+        if let (Some(limit), Some(last)) = (query.limit, result.last()) {
+            if result.len() == limit as usize && !built.order.is_empty() {
+                let mut values = Vec::new();
+                for (i, literal) in built.literal.iter().enumerate() {
+                    let value = if *literal {
+                        last.get::<String>(i)?.map(JValue::from)
+                    } else {
+                        last.get::<i32>(i)?.map(JValue::from)
+                    };
 
-                QueryObject::Id(QueryId::Ignore) => {}
-                QueryObject::Object { value, type_id } => {
-                    others.push((format!("quad_{}.object", i), value));
-                    match type_id {
-                        QueryId::Value(val) => {
-                            checks.insert(format!("quad_{}.type_id", i), val);
-                        }
-                        QueryId::Placeholder(val) => {
-                            if !placeholders.contains_key(&val) {
-                                placeholders.insert(val, vec![format!("quad_{}.type_id", i)]);
-                            } else {
-                                placeholders
-                                    .get_mut(&val)
-                                    .unwrap()
-                                    .push(format!("quad_{}.type_id", i));
-                            }
-                        }
-                        QueryId::Any(any) => {
-                            if any.len() == 0 {
-                                return Ok(vec![]);
-                            }
-
-                            checks_any.insert(format!("quad_{}.type_id", i), any);
-                        }
-                        QueryId::Ignore => {}
-                    }
-                }
-                QueryObject::LanguageString { value, language } => {
-                    others.push((format!("quad_{}.object", i), value.to_owned()));
-                    others.push((format!("quad_{}.language", i), language));
+                    values.push(value.unwrap_or(JValue::Null));
                 }
+
+                cursor = Some(built.cursor(&values));
             }
         }
 
-        let all_attributes = checks
+        Ok(QueryPage {
+            placeholders: built.placeholders,
+            rows: data,
+            cursor,
+        })
+    }
+
+    /// Inserts many (collection, item) pairs in a single round trip, e.g. to fan an activity out
+    /// to many inboxes. Returns the pairs that were not in their collection yet.
+    pub async fn insert_collection_batch(
+        &mut self,
+        pairs: Vec<(String, String)>,
+    ) -> Result<Vec<(String, String)>, StoreError> {
+        let (collections, objects) = self.cache_collection_pairs(&pairs).await?;
+        let inserted = self.insert_collection_many(collections, objects).await?;
+
+        Ok(self.translate_collection_pairs(inserted))
+    }
+
+    /// Removes many (collection, item) pairs in a single round trip. Returns the pairs that were
+    /// actually in their collection.
+    pub async fn remove_collection_batch(
+        &mut self,
+        pairs: Vec<(String, String)>,
+    ) -> Result<Vec<(String, String)>, StoreError> {
+        let (collections, objects) = self.cache_collection_pairs(&pairs).await?;
+        let deleted = self.delete_collection_many(collections, objects).await?;
+
+        Ok(self.translate_collection_pairs(deleted))
+    }
+
+    async fn cache_collection_pairs(
+        &mut self,
+        pairs: &[(String, String)],
+    ) -> Result<(Vec<i32>, Vec<i32>), StoreError> {
+        let uris: Vec<_> = pairs
             .iter()
-            .map(|(_, b)| b.to_owned())
-            .chain(
-                checks_any
-                    .iter()
-                    .flat_map(|(_, b)| b.iter().map(|f| f.to_string())),
-            )
-            .collect::<Vec<_>>();
+            .flat_map(|(a, b)| vec![a.to_owned(), b.to_owned()])
+            .collect();
+        self.cache_uris(&uris).await?;
 
-        self.cache_uris(&all_attributes).await?;
+        Ok(pairs
+            .iter()
+            .map(|(a, b)| (self.cache.uri_to_id[a], self.cache.uri_to_id[b]))
+            .unzip())
+    }
 
-        let mut query = String::from("select ");
+    fn translate_collection_pairs(&self, pairs: Vec<(i32, i32)>) -> Vec<(String, String)> {
+        pairs
+            .into_iter()
+            .map(|(a, b)| {
+                (
+                    self.cache.id_to_uri[&a].to_owned(),
+                    self.cache.id_to_uri[&b].to_owned(),
+                )
+            })
+            .collect()
+    }
 
-        let select_count = placeholders.len();
-        for (i, (_, placeholder)) in placeholders.iter().enumerate() {
-            if i != 0 {
-                query += ", ";
-            }
+    /// Diffs the item against the stored quads, and applies the difference if the version
+    /// matches `expected` (or the version read alongside the quads, if `None`).
+    async fn write_entity(
+        &mut self,
+        path: &str,
+        item: &StoreItem,
+        expected: Option<i64>,
+    ) -> Result<(bool, i64), StoreError> {
+        let rdf = item.clone().to_json();
+
+        let mut rdf = jsonld_to_rdf(&rdf, &mut StoreItemNodeGenerator::new()).unwrap();
+        let mut set = HashSet::new();
 
-            query += &placeholder[0];
+        let quads = rdf.remove("@default").unwrap();
+        for quad in &quads {
+            get_ids(quad, &mut set);
         }
 
-        query += " from ";
+        set.insert(path.to_owned());
 
-        for i in 0..quad_count {
-            if i != 0 {
-                query += ", ";
-            }
+        let set: Vec<_> = set.into_iter().collect();
+        self.cache_uris(&set).await?;
 
-            query += &format!("quad quad_{}", i);
-        }
+        let qid = self.cache.uri_to_id[path];
 
-        query += " where true ";
-        for (a, b) in others {
-            query += &format!("and {} = '{}' ", a, b.replace("'", "''"));
-        }
+        let current = self.read_version(qid).await?;
+        let expected = expected.unwrap_or(current);
+        if expected != current {
+            self.invalidate(path);
 
-        for (_, placeholder) in placeholders {
-            for (a, b) in placeholder.iter().zip(placeholder.iter().skip(1)) {
-                query += &format!("and {} = {} ", a, b);
+            return Err(VersionConflict {
+                path: path.to_owned(),
+                expected,
+                actual: current,
             }
+            .into());
         }
 
-        for (a, b) in checks {
-            let against = self.cache.uri_to_id[&b];
+        // maps the contents of every stored quad to the row IDs holding them
+        let mut existing = HashMap::new();
+        for quad in self.read_quad(qid).await? {
+            existing
+                .entry((quad.subject_id, quad.predicate_id, quad.contents))
+                .or_insert_with(Vec::new)
+                .push(quad.id);
+        }
 
-            query += &format!("and {} = {} ", a, against);
+        let mut subject_id = Vec::new();
+        let mut predicate_id = Vec::new();
+        let mut attribute_id = Vec::new();
+        let mut object = Vec::new();
+        let mut type_id = Vec::new();
+        let mut language = Vec::new();
+
+        for quad in quads {
+            let subject = self.cache.uri_to_id[&quad.subject_id];
+            let predicate = self.cache.uri_to_id[&quad.predicate_id];
+
+            let (attribute, content, typ, languag) = match quad.contents {
+                QuadContents::Id(id) => (Some(self.cache.uri_to_id[&id]), None, None, None),
+                QuadContents::Object(typ_id, content, languag) => (
+                    None,
+                    Some(content),
+                    Some(self.cache.uri_to_id[&typ_id]),
+                    languag,
+                ),
+            };
+
+            let contents = DatabaseQuadContents::from_columns(
+                attribute,
+                content.clone(),
+                typ,
+                languag.clone(),
+            )
+            .unwrap();
+
+            // quads that are already stored are kept as-is
+            if let Some(ids) = existing.get_mut(&(subject, predicate, contents)) {
+                if ids.pop().is_some() {
+                    continue;
+                }
+            }
+
+            subject_id.push(subject);
+            predicate_id.push(predicate);
+            attribute_id.push(attribute);
+            object.push(content);
+            type_id.push(typ);
+            language.push(languag);
         }
 
-        for (a, b) in checks_any {
-            query += &format!(
-                "and {} in ({}) ",
-                a,
-                b.into_iter()
-                    .map(|f| self.cache.uri_to_id[&f].to_string())
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            );
+        let deleted: Vec<i32> = existing.into_iter().flat_map(|(_, ids)| ids).collect();
+        if deleted.is_empty() && subject_id.is_empty() {
+            return Ok((false, current));
         }
 
-        // ok, query built. now send it off
-        let result = self.do_query(query, &[]).await?;
+        let version = self
+            .update_quads(&[
+                &qid,
+                &expected,
+                &deleted,
+                &subject_id,
+                &predicate_id,
+                &attribute_id,
+                &object,
+                &type_id,
+                &language,
+            ])
+            .await?;
 
-        let mut data = vec![];
-        for row in result {
-            let mut row_out = Vec::with_capacity(select_count);
-            for i in 0..select_count {
-                row_out.push(row.get::<i32>(i)?.unwrap());
+        match version {
+            Some(version) => {
+                // the stored form may differ from the item, so the next read caches it instead
+                self.invalidate(path);
+
+                Ok((true, version))
             }
+            None => {
+                self.invalidate(path);
 
-            data.push(row_out);
+                Err(VersionConflict {
+                    path: path.to_owned(),
+                    expected,
+                    actual: self.read_version(qid).await?,
+                }
+                .into())
+            }
         }
+    }
+}
 
-        self.cache_ids(&data.iter().flatten().map(|f| *f).collect::<Vec<_>>())
-            .await?;
+#[async_trait::async_trait]
+/// An entity store, storing JSON-LD `Entity` objects.
+impl<'a> EntityStore for CellarEntityStore<'a> {
+    /// Gets a single `StoreItem` from the store. Missing entities are no error,
+    /// but instead returns a `None`.
+    async fn get(&mut self, path: String, _local: bool) -> Result<Option<StoreItem>, StoreError> {
+        if let Some(item) = self.cached_object(&path) {
+            return Ok(Some(item));
+        }
 
-        Ok(data
+        self.read_entity(path).await
+    }
+
+    /// Stores a single `StoreItem` into the store.
+    ///
+    /// To delete an Entity, set its type to as:Tombstone. This may
+    /// instantly remove it, or queue it for possible future deletion.
+    async fn put(&mut self, path: String, item: &mut StoreItem) -> Result<(), StoreError> {
+        self.update_entity(path, item).await?;
+
+        Ok(())
+    }
+
+    /// Queries the entire store for a specific set of parameters.
+    /// The return value is a list for every result in the database that matches the query.
+    /// The array elements are in numeric order of the placeholders.
+    async fn query(&mut self, query: Vec<QuadQuery>) -> Result<Vec<Vec<String>>, StoreError> {
+        let page = self.query_page(CellarQuery::new(query)).await?;
+
+        // without optional groups or unions, every placeholder is bound
+        Ok(page
+            .rows
             .into_iter()
-            .map(|f| {
-                f.into_iter()
-                    .map(|f| self.cache.id_to_uri[&f].to_owned())
-                    .collect()
-            })
+            .map(|row| row.into_iter().flatten().collect())
             .collect())
     }
 
@@ -329,22 +730,14 @@ impl<'a> EntityStore for CellarEntityStore<'a> {
         count: Option<u32>,
         cursor: Option<String>,
     ) -> Result<CollectionPointer, StoreError> {
-        let (until, offset) = match cursor {
-            None => (true, i32::max_value()),
-            Some(ref value) if value.starts_with("before-") => (false, value[7..].parse::<i32>()?),
-            Some(ref value) if value.starts_with("after-") => (true, value[6..].parse::<i32>()?),
-            _ => return Err("unknown collection cursor".into()),
-        };
+        let (until, offset) = parse_cursor(cursor)?;
 
         self.cache_uris(&[path.to_owned()]).await?;
+        let collection = self.cache.uri_to_id[&path];
         let items = self
-            .select_collection(
-                self.cache.uri_to_id[&path],
-                offset,
-                count.unwrap_or(30) as i32,
-                until,
-            )
+            .select_collection(collection, offset, count.unwrap_or(30) as i32, until)
             .await?;
+        let total = self.collection_count(collection).await?;
 
         let mut all_ids = Vec::new();
         for item in &items {
@@ -353,24 +746,15 @@ impl<'a> EntityStore for CellarEntityStore<'a> {
 
         self.cache_ids(&all_ids).await?;
 
+        let (after, before) = page_cursors(&items, until, offset);
         Ok(CollectionPointer {
             items: items
                 .iter()
                 .map(|f| self.cache.id_to_uri[&f.object_id].to_owned())
                 .collect(),
-            after: items
-                .iter()
-                .last()
-                .and_then(|f| f.id.checked_sub(1))
-                .or(if !until { offset.checked_sub(1) } else { None })
-                .map(|var| format!("after-{}", var)),
-            before: items
-                .iter()
-                .next()
-                .and_then(|f| f.id.checked_add(1))
-                .or(if until { offset.checked_add(1) } else { None })
-                .map(|var| format!("before-{}", var)),
-            count: None,
+            after,
+            before,
+            count: pointer_count(total),
         })
     }
 
@@ -385,19 +769,22 @@ impl<'a> EntityStore for CellarEntityStore<'a> {
         let path_id = self.cache.uri_to_id[&path];
         let item_id = self.cache.uri_to_id[&item];
 
-        if self.collection_contains(path_id, item_id).await? {
+        let total = self.collection_count(path_id).await?;
+
+        // the cursors point at the position of the item in the collection, not the item itself.
+        if let Some(found) = self.find_collection_item(path_id, item_id).await? {
             Ok(CollectionPointer {
                 items: vec![item],
-                after: item_id.checked_sub(1).map(|val| format!("after-{}", val)),
-                before: item_id.checked_add(1).map(|val| format!("before-{}", val)),
-                count: None,
+                after: found.id.checked_sub(1).map(|val| format!("after-{}", val)),
+                before: found.id.checked_add(1).map(|val| format!("before-{}", val)),
+                count: pointer_count(total),
             })
         } else {
             Ok(CollectionPointer {
                 items: vec![],
                 after: None,
                 before: None,
-                count: None,
+                count: pointer_count(total),
             })
         }
     }
@@ -411,31 +798,31 @@ impl<'a> EntityStore for CellarEntityStore<'a> {
         self.insert_collection(path, item).await
     }
 
-    /// Finds all the collections containing a specific object.
+    /// Finds all the collections containing a specific object, reading them page by page.
     async fn read_collection_inverse(
         &mut self,
         item: String,
     ) -> Result<CollectionPointer, StoreError> {
-        self.cache_uris(&[item.to_owned()]).await?;
-        let id = self.cache.uri_to_id[&item];
-        let items = self.select_collection_inverse(id).await?;
+        let mut items = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = self
+                .read_collection_inverse_page(item.to_owned(), Some(100), cursor, None)
+                .await?;
+            let done = page.items.len() < 100;
+
+            items.extend(page.items);
+            if done || page.after.is_none() {
+                return Ok(CollectionPointer {
+                    items,
+                    after: None,
+                    before: None,
+                    count: page.count,
+                });
+            }
 
-        let mut ids = Vec::new();
-        for item in &items {
-            ids.extend_from_slice(&[item.collection_id]);
+            cursor = page.after;
         }
-
-        self.cache_ids(&ids).await?;
-
-        Ok(CollectionPointer {
-            items: items
-                .iter()
-                .map(|f| self.cache.id_to_uri[&f.collection_id].to_owned())
-                .collect(),
-            after: None,
-            before: None,
-            count: None,
-        })
     }
 
     /// Removes an item from the collection.
@@ -1,13 +1,29 @@
 
 mod cache;
+mod cron;
 mod dbquad;
 mod entitystore;
+mod query;
 mod queuestore;
 mod statements;
 mod types;
 
 mod cellarentitystore;
 mod cellarconnection;
+mod listener;
 
+pub use cache::{
+    AttributeCache, ObjectCache, StatementCache, DEFAULT_ATTRIBUTE_CACHE_SIZE,
+    DEFAULT_OBJECT_CACHE_SIZE, DEFAULT_STATEMENT_CACHE_SIZE, ENTITY_CACHE_LIMIT,
+};
+pub use cron::{CronSchedule, Schedule};
 pub use cellarentitystore::CellarEntityStore;
 pub use cellarconnection::CellarConnection;
+pub use entitystore::MAX_WRITE_ATTEMPTS;
+pub use listener::{CellarListener, ENTITY_CHANNEL, QUEUE_CHANNEL};
+pub use query::{CellarQuery, LiteralQuery, QueryFilter, QueryPage};
+pub use queuestore::QueueOptions;
+pub use types::{
+    Enqueued, LeaseLost, OrderedKey, QueueEntry, QueueItemInfo, QueueJob, QueueStats, RecurringJob,
+    VersionConflict,
+};
@@ -0,0 +1,74 @@
+use async_std::net::TcpStream;
+use async_std::task;
+use futures::future::{select, Either};
+use futures::pin_mut;
+use postgres_async::types::AnyError;
+use postgres_async::{Connection, Notification, Statement};
+use std::time::Duration;
+
+/// The channel on which the URI of every changed or deleted entity is sent.
+pub const ENTITY_CHANNEL: &str = "cellar_entity";
+
+/// The channel on which the event of every new queue item is sent.
+pub const QUEUE_CHANNEL: &str = "cellar_queue";
+
+/// A dedicated connection to a Kroeg PostgreSQL-backed database, that receives the notifications
+///  sent by other connections. It cannot be used to run queries. Every notification is only
+///  received by one waiter, so separate listeners should be used for separate purposes.
+pub struct CellarListener {
+    connection: Connection<'static>,
+}
+
+impl CellarListener {
+    /// Connects to a given postgres database over TCP, with no TLS.
+    pub async fn connect(
+        address: &str,
+        username: &str,
+        pass: &str,
+        db: &str,
+    ) -> Result<CellarListener, AnyError> {
+        let stream = TcpStream::connect(address).await?;
+
+        let connection =
+            postgres_async::connect(stream, db.to_owned(), username.to_owned(), pass.to_owned())
+                .await?;
+
+        Ok(CellarListener { connection })
+    }
+
+    /// Starts listening on a channel. Notifications sent before this are not received.
+    pub async fn listen(&self, channel: &str) -> Result<(), AnyError> {
+        if channel.is_empty() || channel.contains('\0') {
+            return Err(format!("invalid channel name {:?}", channel).into());
+        }
+
+        // the channel is an identifier, so it cannot be bound as a parameter
+        let sql = format!("listen \"{}\"", channel.replace('"', "\"\""));
+        let statement = Statement::parse(&self.connection, &sql).await?;
+        let mut bound = statement.bind(&self.connection, &[]).await?;
+        let mut query = bound.execute(&self.connection).await?;
+
+        while let Some(item) = query.next().await {
+            item?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the next notification on any of the channels listened on.
+    pub async fn next(&self) -> Result<Notification, AnyError> {
+        self.connection.next_notification().await
+    }
+
+    /// Waits for the next notification, for at most `timeout`. Returns `None` on timeout.
+    pub async fn wait(&self, timeout: Duration) -> Result<Option<Notification>, AnyError> {
+        let notification = self.connection.next_notification();
+        let sleep = task::sleep(timeout);
+        pin_mut!(notification, sleep);
+
+        match select(notification, sleep).await {
+            Either::Left((notification, _)) => notification.map(Some),
+            Either::Right(_) => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,1061 @@
+use crate::types::{DATETIME_PATTERN, DATETIME_TYPES, NUMBER_PATTERN, NUMERIC_TYPES};
+use kroeg_tap::{QuadQuery, QueryId, QueryObject};
+use postgres_async::types::{AnyError, Serializable};
+use serde_json::Value as JValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+
+/// A query over the quads in the store, like the ones taken by `EntityStore::query`, whose
+/// results can be ordered and read a page at a time.
+pub struct CellarQuery {
+    /// The patterns every result has to match.
+    pub patterns: Vec<QuadQuery>,
+
+    /// Patterns that bind the value of a literal to a placeholder, which every result has to
+    /// match too.
+    pub literals: Vec<LiteralQuery>,
+
+    /// Conditions on the values bound to literal placeholders.
+    pub filters: Vec<QueryFilter>,
+
+    /// Lists of alternative groups of patterns, of which every result has to match at least
+    /// one. A result is returned once for every alternative it matches, unless the results are
+    /// distinct. Placeholders that are not bound by the alternative that matched are `None`.
+    pub union: Vec<Vec<Vec<QuadQuery>>>,
+
+    /// Groups of patterns that are matched if possible, like a left join. Placeholders that
+    /// are only bound by a group are `None` in the results it did not match.
+    pub optional: Vec<Vec<QuadQuery>>,
+
+    /// Groups of patterns that no result may match. Placeholders that are only bound within a
+    /// group are not returned.
+    pub not_exists: Vec<Vec<QuadQuery>>,
+
+    /// If set, every distinct result is only returned once. Results are always distinct if the
+    /// query is ordered, limited or has a cursor, as the cursor could not tell apart duplicates.
+    pub distinct: bool,
+
+    /// The placeholder to order the results by, and whether to order them descending. Results
+    /// with the same value are ordered by the other placeholders, in the same direction.
+    /// Literals are ordered as strings, and IDs by the order they were first stored in, not by
+    /// their URI. Unbound placeholders sort before every value.
+    pub order: Option<(String, bool)>,
+
+    /// The maximum amount of results to return.
+    pub limit: Option<u32>,
+
+    /// The cursor of the previous page, to continue reading after it. It is only valid for
+    /// the query it was returned for.
+    pub cursor: Option<String>,
+}
+
+impl CellarQuery {
+    pub fn new(patterns: Vec<QuadQuery>) -> CellarQuery {
+        CellarQuery {
+            patterns,
+            literals: Vec::new(),
+            filters: Vec::new(),
+            union: Vec::new(),
+            optional: Vec::new(),
+            not_exists: Vec::new(),
+            distinct: false,
+            order: None,
+            limit: None,
+            cursor: None,
+        }
+    }
+}
+
+/// A pattern matching quads with a literal object, like `QueryObject::Object`, but binding
+/// the value of the literal to a placeholder instead of matching it exactly. Literal
+/// placeholders cannot be used in place of an ID.
+pub struct LiteralQuery {
+    pub subject: QueryId,
+    pub predicate: QueryId,
+
+    /// The placeholder the value of the literal is bound to.
+    pub value: String,
+
+    pub type_id: QueryId,
+
+    /// If set, the placeholder the language of the literal is bound to.
+    pub language: Option<String>,
+}
+
+/// A condition on the value bound to a literal placeholder. `LessThan` and `GreaterThan`
+/// compare values as numbers if the literal pattern only matches XSD numeric types, and as
+/// points in time if it only matches `xsd:dateTime`, in which case values that are not valid
+/// for their type, including out of range ones like `1e1000000`, never match. Other values are
+/// compared as strings.
+#[derive(Debug, Clone)]
+pub enum QueryFilter {
+    LessThan(String, String),
+    GreaterThan(String, String),
+    Prefix(String, String),
+
+    /// Matches a SQL `LIKE` pattern, where `%` matches any string, and `_` any character.
+    Like(String, String),
+
+    /// Matches a POSIX regular expression.
+    Regex(String, String),
+
+    /// The placeholder is bound to a language that matches a language range like `en`, which
+    /// matches both `en` and `en-US`. The range `*` matches every language.
+    Language(String, String),
+}
+
+/// A page of results of a `CellarQuery`.
+#[derive(Debug, Clone)]
+pub struct QueryPage {
+    /// The placeholders of the query, in the order their values are in every row.
+    pub placeholders: Vec<String>,
+
+    pub rows: Vec<Vec<Option<String>>>,
+
+    /// If the page is full, the cursor to read the next page with.
+    pub cursor: Option<String>,
+}
+
+/// The SQL generated for a `CellarQuery`, with the parameters to bind it with.
+pub(crate) struct BuiltQuery {
+    pub sql: String,
+    pub params: Vec<Box<dyn Serializable>>,
+
+    /// The placeholders, in the order they are selected.
+    pub placeholders: Vec<String>,
+
+    /// For every placeholder, whether it is bound to a literal instead of an ID.
+    pub literal: Vec<bool>,
+
+    /// The indices of the selected columns the results are ordered by, most significant first.
+    pub order: Vec<usize>,
+}
+
+impl BuiltQuery {
+    /// Makes the cursor to read the page after a row, from the values of its columns. IDs are
+    /// numbers, literals strings, and unbound placeholders null.
+    pub fn cursor(&self, row: &[JValue]) -> String {
+        let keys: Vec<_> = self.order.iter().map(|i| row[*i].clone()).collect();
+
+        JValue::from(keys).to_string()
+    }
+}
+
+/// Collects every URI used in the patterns, which have to be cached before building the query.
+pub(crate) fn query_uris(query: &CellarQuery) -> Vec<String> {
+    let mut out = Vec::new();
+
+    let groups = query
+        .union
+        .iter()
+        .flatten()
+        .chain(&query.optional)
+        .chain(&query.not_exists);
+
+    for QuadQuery(subject, predicate, object) in query.patterns.iter().chain(groups.flatten()) {
+        id_uris(subject, &mut out);
+        id_uris(predicate, &mut out);
+
+        match object {
+            QueryObject::Id(id) => id_uris(id, &mut out),
+            QueryObject::Object { type_id, .. } => id_uris(type_id, &mut out),
+            QueryObject::LanguageString { .. } => {}
+        }
+    }
+
+    for literal in &query.literals {
+        id_uris(&literal.subject, &mut out);
+        id_uris(&literal.predicate, &mut out);
+        id_uris(&literal.type_id, &mut out);
+    }
+
+    out
+}
+
+/// The SQL type and valid pattern of the values of literals that can only have `type_id`, if
+/// they are compared as something other than strings.
+fn type_cast(type_id: &QueryId) -> Option<(&'static str, &'static str)> {
+    let types: Vec<_> = match type_id {
+        QueryId::Value(val) => vec![val.as_str()],
+        QueryId::Any(any) if !any.is_empty() => any.iter().map(|f| f.as_str()).collect(),
+        _ => return None,
+    };
+
+    if types.iter().all(|f| NUMERIC_TYPES.contains(f)) {
+        Some(("numeric", NUMBER_PATTERN))
+    } else if types.iter().all(|f| DATETIME_TYPES.contains(f)) {
+        Some(("timestamptz", DATETIME_PATTERN))
+    } else {
+        None
+    }
+}
+
+fn id_uris(id: &QueryId, out: &mut Vec<String>) {
+    match id {
+        QueryId::Value(val) => out.push(val.to_owned()),
+        QueryId::Any(any) => out.extend(any.iter().cloned()),
+        QueryId::Placeholder(_) | QueryId::Ignore => {}
+    }
+}
+
+/// The tables and conditions of a query or subquery, and the column every placeholder was
+/// first bound to. Placeholders bound in `outer` refer to the query around a subquery.
+#[derive(Default)]
+struct Scope {
+    tables: Vec<String>,
+    joins: Vec<String>,
+    conditions: Vec<String>,
+    bound: BTreeMap<String, String>,
+    outer: BTreeMap<String, String>,
+
+    /// The placeholders bound to a literal instead of an ID, in this scope or in `outer`.
+    literals: BTreeSet<String>,
+}
+
+impl Scope {
+    /// Binds a placeholder to a column, or matches the column against it if it is bound
+    /// already. Fails if it is bound to an ID as well as a literal.
+    fn bind(&mut self, placeholder: &str, column: String, literal: bool) -> Result<(), AnyError> {
+        let existing = self
+            .bound
+            .get(placeholder)
+            .or_else(|| self.outer.get(placeholder))
+            .cloned();
+
+        match existing {
+            Some(_) if self.literals.contains(placeholder) != literal => {
+                return Err(format!(
+                    "placeholder {} is bound to both an ID and a literal",
+                    placeholder
+                )
+                .into())
+            }
+
+            Some(existing) => self.conditions.push(format!("{} = {}", column, existing)),
+            None => {
+                if literal {
+                    self.literals.insert(placeholder.to_owned());
+                }
+
+                self.bound.insert(placeholder.to_owned(), column);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The from and where clauses of the scope.
+    fn from_where(&self) -> String {
+        let mut sql = if self.tables.is_empty() {
+            String::from(" from (select) base")
+        } else {
+            format!(" from {}", self.tables.join(" cross join "))
+        };
+
+        for join in &self.joins {
+            sql += join;
+        }
+
+        sql += " where true";
+        for condition in &self.conditions {
+            sql += " and ";
+            sql += condition;
+        }
+
+        sql
+    }
+}
+
+struct Builder<'a> {
+    ids: &'a HashMap<String, i32>,
+    params: Vec<Box<dyn Serializable>>,
+    tables: usize,
+
+    /// The type cast of literal placeholders that are compared as something other than strings.
+    casts: BTreeMap<String, (&'static str, &'static str)>,
+}
+
+impl<'a> Builder<'a> {
+    /// Adds a parameter, returning the SQL referring to it. Values are never put into the SQL
+    /// itself, so the same shape of query always generates the same SQL.
+    fn param<T: Serializable + 'static>(&mut self, value: T, cast: &str) -> String {
+        self.params.push(Box::new(value));
+
+        format!("${}::{}", self.params.len(), cast)
+    }
+
+    fn id(&mut self, scope: &mut Scope, column: String, id: &QueryId) -> Result<(), AnyError> {
+        match id {
+            QueryId::Value(val) => {
+                let param = self.param(self.ids[val], "int");
+                scope.conditions.push(format!("{} = {}", column, param));
+            }
+
+            QueryId::Any(any) => {
+                let ids: Vec<_> = any.iter().map(|f| self.ids[f]).collect();
+                let param = self.param(ids, "int[]");
+                scope
+                    .conditions
+                    .push(format!("{} = any({})", column, param));
+            }
+
+            QueryId::Placeholder(val) => scope.bind(val, column, false)?,
+            QueryId::Ignore => {}
+        }
+
+        Ok(())
+    }
+
+    fn text(&mut self, scope: &mut Scope, column: String, value: &str) {
+        let param = self.param(value.to_owned(), "text");
+        scope.conditions.push(format!("{} = {}", column, param));
+    }
+
+    fn literal(&mut self, scope: &mut Scope, literal: &LiteralQuery) -> Result<(), AnyError> {
+        let table = format!("quad_{}", self.tables);
+        self.tables += 1;
+        scope.tables.push(format!("quad {}", table));
+
+        self.id(scope, format!("{}.quad_id", table), &literal.subject)?;
+        self.id(scope, format!("{}.predicate_id", table), &literal.predicate)?;
+        self.id(scope, format!("{}.type_id", table), &literal.type_id)?;
+        scope
+            .conditions
+            .push(format!("{}.attribute_id is null", table));
+
+        scope.bind(&literal.value, format!("{}.object", table), true)?;
+        if let Some(cast) = type_cast(&literal.type_id) {
+            self.casts.entry(literal.value.to_owned()).or_insert(cast);
+        }
+
+        if let Some(language) = &literal.language {
+            scope.bind(language, format!("{}.language", table), true)?;
+        }
+
+        Ok(())
+    }
+
+    fn filter(&mut self, scope: &mut Scope, filter: &QueryFilter) -> Result<(), AnyError> {
+        let (placeholder, value) = match filter {
+            QueryFilter::LessThan(placeholder, value)
+            | QueryFilter::GreaterThan(placeholder, value)
+            | QueryFilter::Prefix(placeholder, value)
+            | QueryFilter::Like(placeholder, value)
+            | QueryFilter::Regex(placeholder, value)
+            | QueryFilter::Language(placeholder, value) => (placeholder, value),
+        };
+
+        if !scope.literals.contains(placeholder) {
+            return Err(format!(
+                "cannot filter on placeholder {}, as it is not a literal",
+                placeholder
+            )
+            .into());
+        }
+
+        let mut column = scope.bound[placeholder].to_owned();
+        let mut value = self.param(value.to_owned(), "text");
+
+        if let (QueryFilter::LessThan(..), Some((cast, pattern)))
+        | (QueryFilter::GreaterThan(..), Some((cast, pattern))) =
+            (filter, self.casts.get(placeholder).copied())
+        {
+            let pattern = self.param(pattern.to_owned(), "text");
+            column = format!(
+                "(case when {0} ~ {1} then try_{2}({0}) end)",
+                column, pattern, cast
+            );
+            value = format!("{}::{}", value, cast);
+        }
+
+        scope.conditions.push(match filter {
+            QueryFilter::LessThan(..) => format!("{} < {}", column, value),
+            QueryFilter::GreaterThan(..) => format!("{} > {}", column, value),
+            QueryFilter::Prefix(..) => format!("left({}, length({})) = {}", column, value, value),
+            QueryFilter::Like(..) => format!("{} like {}", column, value),
+            QueryFilter::Regex(..) => format!("{} ~ {}", column, value),
+            QueryFilter::Language(..) => format!(
+                "({1} = '*' and {0} <> '' or lower({0}) = lower({1}) or left(lower({0}), length({1}) + 1) = lower({1}) || '-')",
+                column, value
+            ),
+        });
+
+        Ok(())
+    }
+
+    /// Builds a subquery that may refer to the placeholders bound in `outer`.
+    fn group(&mut self, patterns: &[QuadQuery], outer: &Scope) -> Result<Scope, AnyError> {
+        let mut scope = Scope {
+            outer: outer.bound.clone(),
+            literals: outer.literals.clone(),
+            ..Scope::default()
+        };
+
+        for pattern in patterns {
+            self.pattern(&mut scope, pattern)?;
+        }
+
+        Ok(scope)
+    }
+
+    fn pattern(&mut self, scope: &mut Scope, pattern: &QuadQuery) -> Result<(), AnyError> {
+        let QuadQuery(subject, predicate, object) = pattern;
+
+        let table = format!("quad_{}", self.tables);
+        self.tables += 1;
+        scope.tables.push(format!("quad {}", table));
+
+        self.id(scope, format!("{}.quad_id", table), subject)?;
+        self.id(scope, format!("{}.predicate_id", table), predicate)?;
+
+        match object {
+            QueryObject::Id(id) => self.id(scope, format!("{}.attribute_id", table), id)?,
+            QueryObject::Object { value, type_id } => {
+                self.text(scope, format!("{}.object", table), value);
+                self.id(scope, format!("{}.type_id", table), type_id)?;
+            }
+            QueryObject::LanguageString { value, language } => {
+                self.text(scope, format!("{}.object", table), value);
+                self.text(scope, format!("{}.language", table), language);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Generates the SQL for a query. `ids` has to contain every URI returned by `query_uris`.
+pub(crate) fn build_query(
+    query: &CellarQuery,
+    ids: &HashMap<String, i32>,
+) -> Result<BuiltQuery, AnyError> {
+    let mut builder = Builder {
+        ids,
+        params: Vec::new(),
+        tables: 0,
+        casts: BTreeMap::new(),
+    };
+
+    let mut scope = Scope::default();
+    for pattern in &query.patterns {
+        builder.pattern(&mut scope, pattern)?;
+    }
+
+    for literal in &query.literals {
+        builder.literal(&mut scope, literal)?;
+    }
+
+    // unions come first, so optional groups can refer to the placeholders bound by them
+    for (i, alternatives) in query.union.iter().enumerate() {
+        if alternatives.is_empty() {
+            scope.conditions.push(String::from("false"));
+            continue;
+        }
+
+        let mut groups = Vec::new();
+        for patterns in alternatives {
+            groups.push(builder.group(patterns, &scope)?);
+        }
+
+        let names: BTreeSet<_> = groups
+            .iter()
+            .flat_map(|group| group.bound.keys().cloned())
+            .collect();
+
+        let selects: Vec<_> = groups
+            .iter()
+            .map(|group| {
+                let columns: Vec<_> = names
+                    .iter()
+                    .enumerate()
+                    .map(|(j, name)| match group.bound.get(name) {
+                        Some(column) => format!("{} as v{}", column, j),
+                        None => format!("null::int as v{}", j),
+                    })
+                    .collect();
+
+                format!("select {}{}", columns.join(", "), group.from_where())
+            })
+            .collect();
+
+        scope.joins.push(format!(
+            " cross join lateral ({}) union_{}",
+            selects.join(" union all "),
+            i
+        ));
+
+        for (j, name) in names.into_iter().enumerate() {
+            scope.bound.insert(name, format!("union_{}.v{}", i, j));
+        }
+    }
+
+    for (i, patterns) in query.optional.iter().enumerate() {
+        let group = builder.group(patterns, &scope)?;
+        let columns: Vec<_> = group
+            .bound
+            .values()
+            .enumerate()
+            .map(|(j, column)| format!("{} as v{}", column, j))
+            .collect();
+
+        scope.joins.push(format!(
+            " left join lateral (select {}{}) optional_{} on true",
+            columns.join(", "),
+            group.from_where(),
+            i
+        ));
+
+        for (j, name) in group.bound.keys().enumerate() {
+            scope
+                .bound
+                .insert(name.to_owned(), format!("optional_{}.v{}", i, j));
+        }
+    }
+
+    for patterns in &query.not_exists {
+        let group = builder.group(patterns, &scope)?;
+
+        scope
+            .conditions
+            .push(format!("not exists (select 1{})", group.from_where()));
+    }
+
+    for filter in &query.filters {
+        builder.filter(&mut scope, filter)?;
+    }
+
+    let placeholders: Vec<_> = scope.bound.keys().cloned().collect();
+    let literal: Vec<_> = placeholders
+        .iter()
+        .map(|f| scope.literals.contains(f))
+        .collect();
+    let columns: Vec<_> = scope
+        .bound
+        .values()
+        .enumerate()
+        .map(|(i, column)| format!("{} as c{}", column, i))
+        .collect();
+
+    let paged = query.order.is_some() || query.limit.is_some() || query.cursor.is_some();
+    let sql = format!(
+        "select {}{}{}",
+        if query.distinct || paged {
+            "distinct "
+        } else {
+            ""
+        },
+        columns.join(", "),
+        scope.from_where()
+    );
+
+    let mut order: Vec<_> = (0..placeholders.len()).collect();
+    let descending = match &query.order {
+        Some((placeholder, descending)) => {
+            let index = placeholders
+                .iter()
+                .position(|f| f == placeholder)
+                .ok_or("unknown placeholder to order by")?;
+
+            order.remove(index);
+            order.insert(0, index);
+
+            *descending
+        }
+
+        None => false,
+    };
+
+    // plain queries are returned unordered, like before
+    if !paged {
+        return Ok(BuiltQuery {
+            sql,
+            params: builder.params,
+            placeholders,
+            literal,
+            order,
+        });
+    }
+
+    // unbound placeholders sort before every value. as the results are distinct, the keys of
+    // every result are unique.
+    let mut keys = Vec::new();
+    for i in &order {
+        keys.push(format!("r.c{} is not null", i));
+        keys.push(if literal[*i] {
+            format!("coalesce(r.c{}, '')", i)
+        } else {
+            format!("coalesce(r.c{}, 0)", i)
+        });
+    }
+    let selected: Vec<_> = (0..placeholders.len())
+        .map(|i| format!("r.c{}", i))
+        .collect();
+
+    let mut sql = format!("select {} from ({}) r", selected.join(", "), sql);
+
+    if let (Some(cursor), false) = (&query.cursor, keys.is_empty()) {
+        let values: Vec<JValue> =
+            serde_json::from_str(cursor).map_err(|_| "unknown query cursor")?;
+        if values.len() != order.len() {
+            return Err("unknown query cursor".into());
+        }
+
+        let mut bound = Vec::new();
+        for (i, value) in order.iter().zip(values) {
+            bound.push(builder.param(!value.is_null(), "bool"));
+            bound.push(match (literal[*i], value) {
+                (true, JValue::Null) => builder.param(String::new(), "text"),
+                (false, JValue::Null) => builder.param(0, "int"),
+                (true, JValue::String(value)) => builder.param(value, "text"),
+                (false, JValue::Number(value)) => {
+                    let value = value
+                        .as_i64()
+                        .and_then(|f| i32::try_from(f).ok())
+                        .ok_or("unknown query cursor")?;
+
+                    builder.param(value, "int")
+                }
+                _ => return Err("unknown query cursor".into()),
+            });
+        }
+
+        sql += &format!(
+            " where ({}) {} ({})",
+            keys.join(", "),
+            if descending { "<" } else { ">" },
+            bound.join(", ")
+        );
+    }
+
+    if !keys.is_empty() {
+        let direction = if descending { " desc" } else { " asc" };
+        let keys: Vec<_> = keys.iter().map(|f| format!("{}{}", f, direction)).collect();
+
+        sql += " order by ";
+        sql += &keys.join(", ");
+    }
+
+    if let Some(limit) = query.limit {
+        sql += &format!(" limit {}", builder.param(limit as i64, "bigint"));
+    }
+
+    Ok(BuiltQuery {
+        sql,
+        params: builder.params,
+        placeholders,
+        literal,
+        order,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids() -> HashMap<String, i32> {
+        let mut ids = HashMap::new();
+        ids.insert(String::from("p"), 5);
+        ids.insert(String::from("q"), 6);
+        ids
+    }
+
+    fn placeholder(name: &str) -> QueryId {
+        QueryId::Placeholder(name.to_owned())
+    }
+
+    fn value(uri: &str) -> QueryId {
+        QueryId::Value(uri.to_owned())
+    }
+
+    /// The `s p o` pattern, binding `o` to `quad_0.attribute_id` and `s` to `quad_0.quad_id`.
+    fn base_query() -> CellarQuery {
+        CellarQuery::new(vec![QuadQuery(
+            placeholder("s"),
+            value("p"),
+            QueryObject::Id(placeholder("o")),
+        )])
+    }
+
+    fn serialized(value: &dyn Serializable) -> Vec<u8> {
+        let mut buf = Vec::new();
+        value.serialize(&mut buf);
+        buf
+    }
+
+    fn params(built: &BuiltQuery) -> Vec<Vec<u8>> {
+        built
+            .params
+            .iter()
+            .map(|f| serialized(f.as_ref()))
+            .collect()
+    }
+
+    #[test]
+    fn plain_query_is_unordered() {
+        let built = build_query(&base_query(), &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select quad_0.attribute_id as c0, quad_0.quad_id as c1 from quad quad_0 where true and quad_0.predicate_id = $1::int"
+        );
+        assert_eq!(params(&built), vec![serialized(&5)]);
+        assert_eq!(built.placeholders, vec!["o", "s"]);
+        assert_eq!(built.literal, vec![false, false]);
+    }
+
+    #[test]
+    fn distinct_query() {
+        let mut query = base_query();
+        query.distinct = true;
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select distinct quad_0.attribute_id as c0, quad_0.quad_id as c1 from quad quad_0 where true and quad_0.predicate_id = $1::int"
+        );
+    }
+
+    #[test]
+    fn limited_query_is_distinct_and_ordered() {
+        let mut query = base_query();
+        query.limit = Some(10);
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select r.c0, r.c1 from (select distinct quad_0.attribute_id as c0, quad_0.quad_id as c1 from quad quad_0 where true and quad_0.predicate_id = $1::int) r order by r.c0 is not null asc, coalesce(r.c0, 0) asc, r.c1 is not null asc, coalesce(r.c1, 0) asc limit $2::bigint"
+        );
+        assert_eq!(params(&built), vec![serialized(&5), serialized(&10i64)]);
+        assert_eq!(built.order, vec![0, 1]);
+    }
+
+    #[test]
+    fn ordered_query() {
+        let mut query = base_query();
+        query.order = Some((String::from("s"), true));
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select r.c0, r.c1 from (select distinct quad_0.attribute_id as c0, quad_0.quad_id as c1 from quad quad_0 where true and quad_0.predicate_id = $1::int) r order by r.c1 is not null desc, coalesce(r.c1, 0) desc, r.c0 is not null desc, coalesce(r.c0, 0) desc"
+        );
+        assert_eq!(built.order, vec![1, 0]);
+
+        query.order = Some((String::from("x"), false));
+        assert!(build_query(&query, &ids()).is_err());
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let mut query = base_query();
+        query.order = Some((String::from("s"), true));
+        query.limit = Some(2);
+        let built = build_query(&query, &ids()).unwrap();
+
+        let cursor = built.cursor(&[JValue::Null, JValue::from(7)]);
+        assert_eq!(cursor, "[7,null]");
+
+        query.cursor = Some(cursor);
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select r.c0, r.c1 from (select distinct quad_0.attribute_id as c0, quad_0.quad_id as c1 from quad quad_0 where true and quad_0.predicate_id = $1::int) r where (r.c1 is not null, coalesce(r.c1, 0), r.c0 is not null, coalesce(r.c0, 0)) < ($2::bool, $3::int, $4::bool, $5::int) order by r.c1 is not null desc, coalesce(r.c1, 0) desc, r.c0 is not null desc, coalesce(r.c0, 0) desc limit $6::bigint"
+        );
+        assert_eq!(
+            params(&built),
+            vec![
+                serialized(&5),
+                serialized(&true),
+                serialized(&7),
+                serialized(&false),
+                serialized(&0),
+                serialized(&2i64),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_cursors() {
+        let mut query = base_query();
+        query.limit = Some(2);
+
+        for cursor in &["", "{}", "[1]", "[1,2,3]", "[\"a\",1]", "[1,4294967296]"] {
+            query.cursor = Some(cursor.to_string());
+            assert!(build_query(&query, &ids()).is_err(), "{}", cursor);
+        }
+    }
+
+    #[test]
+    fn empty_any_matches_nothing() {
+        let query = CellarQuery::new(vec![QuadQuery(
+            placeholder("s"),
+            QueryId::Any(Vec::new()),
+            QueryObject::Id(QueryId::Ignore),
+        )]);
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select quad_0.quad_id as c0 from quad quad_0 where true and quad_0.predicate_id = any($1::int[])"
+        );
+        assert_eq!(params(&built), vec![serialized(&Vec::<i32>::new())]);
+    }
+
+    #[test]
+    fn union_groups() {
+        let mut query = base_query();
+        query.union = vec![vec![
+            vec![QuadQuery(
+                placeholder("s"),
+                value("q"),
+                QueryObject::Id(placeholder("a")),
+            )],
+            vec![QuadQuery(
+                placeholder("o"),
+                value("q"),
+                QueryObject::Id(placeholder("b")),
+            )],
+        ]];
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select union_0.v0 as c0, union_0.v1 as c1, quad_0.attribute_id as c2, quad_0.quad_id as c3 from quad quad_0 cross join lateral (select quad_1.attribute_id as v0, null::int as v1 from quad quad_1 where true and quad_1.quad_id = quad_0.quad_id and quad_1.predicate_id = $2::int union all select null::int as v0, quad_2.attribute_id as v1 from quad quad_2 where true and quad_2.quad_id = quad_0.attribute_id and quad_2.predicate_id = $3::int) union_0 where true and quad_0.predicate_id = $1::int"
+        );
+        assert_eq!(
+            params(&built),
+            vec![serialized(&5), serialized(&6), serialized(&6)]
+        );
+        assert_eq!(built.placeholders, vec!["a", "b", "o", "s"]);
+    }
+
+    #[test]
+    fn empty_union_matches_nothing() {
+        let mut query = base_query();
+        query.union = vec![vec![]];
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select quad_0.attribute_id as c0, quad_0.quad_id as c1 from quad quad_0 where true and quad_0.predicate_id = $1::int and false"
+        );
+    }
+
+    #[test]
+    fn optional_group() {
+        let mut query = base_query();
+        query.optional = vec![vec![QuadQuery(
+            placeholder("o"),
+            value("q"),
+            QueryObject::Id(placeholder("a")),
+        )]];
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select optional_0.v0 as c0, quad_0.attribute_id as c1, quad_0.quad_id as c2 from quad quad_0 left join lateral (select quad_1.attribute_id as v0 from quad quad_1 where true and quad_1.quad_id = quad_0.attribute_id and quad_1.predicate_id = $2::int) optional_0 on true where true and quad_0.predicate_id = $1::int"
+        );
+        assert_eq!(params(&built), vec![serialized(&5), serialized(&6)]);
+        assert_eq!(built.placeholders, vec!["a", "o", "s"]);
+    }
+
+    #[test]
+    fn not_exists_group() {
+        let mut query = base_query();
+        query.not_exists = vec![vec![QuadQuery(
+            placeholder("o"),
+            value("q"),
+            QueryObject::Id(placeholder("z")),
+        )]];
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select quad_0.attribute_id as c0, quad_0.quad_id as c1 from quad quad_0 where true and quad_0.predicate_id = $1::int and not exists (select 1 from quad quad_1 where true and quad_1.quad_id = quad_0.attribute_id and quad_1.predicate_id = $2::int)"
+        );
+        assert_eq!(built.placeholders, vec!["o", "s"]);
+    }
+
+    fn literal(subject: QueryId, value: &str) -> LiteralQuery {
+        LiteralQuery {
+            subject,
+            predicate: QueryId::Value(String::from("q")),
+            value: value.to_owned(),
+            type_id: QueryId::Ignore,
+            language: None,
+        }
+    }
+
+    #[test]
+    fn literal_placeholders() {
+        let mut query = base_query();
+        query.literals = vec![literal(placeholder("s"), "v")];
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert_eq!(
+            built.sql,
+            "select quad_0.attribute_id as c0, quad_0.quad_id as c1, quad_1.object as c2 from quad quad_0 cross join quad quad_1 where true and quad_0.predicate_id = $1::int and quad_1.quad_id = quad_0.quad_id and quad_1.predicate_id = $2::int and quad_1.attribute_id is null"
+        );
+        assert_eq!(built.literal, vec![false, false, true]);
+    }
+
+    #[test]
+    fn placeholders_are_either_ids_or_literals() {
+        let mut query = base_query();
+        query.literals = vec![literal(placeholder("s"), "o")];
+        assert!(build_query(&query, &ids()).is_err());
+
+        let mut query = base_query();
+        query.literals = vec![
+            literal(placeholder("s"), "v"),
+            literal(placeholder("v"), "w"),
+        ];
+        assert!(build_query(&query, &ids()).is_err());
+
+        let group = vec![QuadQuery(
+            placeholder("v"),
+            value("q"),
+            QueryObject::Id(QueryId::Ignore),
+        )];
+
+        let mut query = base_query();
+        query.literals = vec![literal(placeholder("s"), "v")];
+        query.optional = vec![group.clone()];
+        assert!(build_query(&query, &ids()).is_err());
+
+        let mut query = base_query();
+        query.literals = vec![literal(placeholder("s"), "v")];
+        query.not_exists = vec![group.clone()];
+        assert!(build_query(&query, &ids()).is_err());
+
+        let mut query = base_query();
+        query.literals = vec![literal(placeholder("s"), "v")];
+        query.union = vec![vec![group]];
+        assert!(build_query(&query, &ids()).is_err());
+    }
+
+    const INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+    const DATETIME: &str = "http://www.w3.org/2001/XMLSchema#dateTime";
+
+    /// Builds `base_query` with a literal `v` of type `type_id` on `s`, filtered by `filter`.
+    /// Returns the condition of the filter, and its parameters.
+    fn filtered(filter: QueryFilter, type_id: QueryId) -> Result<(String, Vec<Vec<u8>>), AnyError> {
+        let mut ids = ids();
+        ids.insert(String::from(INTEGER), 7);
+        ids.insert(String::from(DATETIME), 8);
+
+        let mut query = base_query();
+        query.literals = vec![LiteralQuery {
+            type_id,
+            ..literal(placeholder("s"), "v")
+        }];
+        query.filters = vec![filter];
+
+        let built = build_query(&query, &ids)?;
+        let index = built.sql.rfind(" and ").unwrap();
+
+        Ok((built.sql[index + 5..].to_owned(), params(&built)))
+    }
+
+    fn text(value: &str) -> Vec<u8> {
+        serialized(&value.to_owned())
+    }
+
+    #[test]
+    fn string_filters() {
+        let filter = |filter| filtered(filter, QueryId::Ignore).unwrap();
+
+        assert_eq!(
+            filter(QueryFilter::LessThan("v".into(), "b".into())),
+            (
+                "quad_1.object < $3::text".into(),
+                vec![serialized(&5), serialized(&6), text("b")]
+            )
+        );
+        assert_eq!(
+            filter(QueryFilter::GreaterThan("v".into(), "b".into())).0,
+            "quad_1.object > $3::text"
+        );
+        assert_eq!(
+            filter(QueryFilter::Prefix("v".into(), "b".into())).0,
+            "left(quad_1.object, length($3::text)) = $3::text"
+        );
+        assert_eq!(
+            filter(QueryFilter::Like("v".into(), "b%".into())).0,
+            "quad_1.object like $3::text"
+        );
+        assert_eq!(
+            filter(QueryFilter::Regex("v".into(), "^b".into())).0,
+            "quad_1.object ~ $3::text"
+        );
+    }
+
+    #[test]
+    fn language_filter() {
+        let mut query = base_query();
+        query.literals = vec![LiteralQuery {
+            language: Some(String::from("l")),
+            ..literal(placeholder("s"), "v")
+        }];
+        query.filters = vec![QueryFilter::Language("l".into(), "en".into())];
+        let built = build_query(&query, &ids()).unwrap();
+
+        assert!(built.sql.ends_with(
+            " and ($3::text = '*' and quad_1.language <> '' or lower(quad_1.language) = lower($3::text) or left(lower(quad_1.language), length($3::text) + 1) = lower($3::text) || '-')"
+        ));
+        assert_eq!(built.placeholders, vec!["l", "o", "s", "v"]);
+        assert_eq!(built.literal, vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn typed_comparisons() {
+        let integer = QueryId::Value(String::from(INTEGER));
+        let datetime = QueryId::Any(vec![String::from(DATETIME)]);
+
+        assert_eq!(
+            filtered(QueryFilter::LessThan("v".into(), "10".into()), integer.clone()).unwrap(),
+            (
+                "(case when quad_1.object ~ $5::text then try_numeric(quad_1.object) end) < $4::text::numeric".into(),
+                vec![
+                    serialized(&5),
+                    serialized(&6),
+                    serialized(&7),
+                    text("10"),
+                    text(NUMBER_PATTERN),
+                ]
+            )
+        );
+        assert_eq!(
+            filtered(QueryFilter::GreaterThan("v".into(), "2021-03-04T10:30:00Z".into()), datetime)
+                .unwrap()
+                .0,
+            "(case when quad_1.object ~ $5::text then try_timestamptz(quad_1.object) end) > $4::text::timestamptz"
+        );
+
+        // other filters, and literals of mixed types, are still compared as strings
+        assert_eq!(
+            filtered(QueryFilter::Prefix("v".into(), "1".into()), integer)
+                .unwrap()
+                .0,
+            "left(quad_1.object, length($4::text)) = $4::text"
+        );
+        assert_eq!(
+            filtered(
+                QueryFilter::LessThan("v".into(), "10".into()),
+                QueryId::Any(vec![String::from(INTEGER), String::from(DATETIME)])
+            )
+            .unwrap()
+            .0,
+            "quad_1.object < $4::text"
+        );
+    }
+
+    #[test]
+    fn filters_need_literal_placeholders() {
+        for name in &["o", "x"] {
+            let filter = QueryFilter::LessThan(name.to_string(), "b".into());
+            assert!(filtered(filter, QueryId::Ignore).is_err());
+        }
+    }
+}
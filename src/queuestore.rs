@@ -1,21 +1,154 @@
-use crate::CellarEntityStore;
+use crate::{CellarEntityStore, CellarListener, Enqueued, LeaseLost, QueueJob};
 use kroeg_tap::{QueueItem, QueueStore, StoreError};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Configures how a `CellarEntityStore` claims and retries queue items.
+#[derive(Debug, Clone)]
+pub struct QueueOptions {
+    /// How long a claimed item stays hidden from other workers. If it is neither marked as a
+    ///  success nor as a failure by then, the worker is assumed to have crashed.
+    pub visibility_timeout: Duration,
+
+    /// The amount of attempts after which an item is moved to the dead-letter state.
+    pub max_attempts: i32,
+
+    /// The delay before the first retry of a failed item. Every following failure doubles it,
+    ///  and a random jitter of up to half the delay is subtracted.
+    pub backoff_base: Duration,
+
+    /// The longest delay between two retries of a failed item.
+    pub backoff_max: Duration,
+
+    /// If set, only items with one of these events are claimed. This allows dedicating workers
+    ///  to specific lanes of the queue.
+    pub events: Option<Vec<String>>,
+
+    /// If set, no more than this amount of items with the same group are claimed at once.
+    ///  This is enforced per claim, so concurrent workers may briefly exceed it. Claims that
+    ///  timed out keep counting until the item is claimed again.
+    pub max_in_flight_per_group: Option<i32>,
+
+    /// How long the idempotency key of an item is remembered after it was added.
+    pub dedup_window: Duration,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        QueueOptions {
+            visibility_timeout: Duration::from_secs(300),
+            max_attempts: 10,
+            backoff_base: Duration::from_secs(10),
+            backoff_max: Duration::from_secs(6 * 60 * 60),
+            events: None,
+            max_in_flight_per_group: None,
+            dedup_window: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl<'a> CellarEntityStore<'a> {
+    /// Gets an item from the queue like `QueueStore::get_item`, but waits for at most `timeout`
+    /// for one to be added if the queue is empty. The listener has to be listening on
+    /// `QUEUE_CHANNEL`, and should not be used for anything else.
+    pub async fn wait_item(
+        &mut self,
+        listener: &CellarListener,
+        timeout: Duration,
+    ) -> Result<Option<QueueItem>, StoreError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(item) = self.get_item().await? {
+                return Ok(Some(item));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+
+            listener.wait(deadline - now).await?;
+        }
+    }
+
+    /// Adds an item to the queue like `QueueStore::add`, unless an item with the same idempotency
+    /// key was added within the deduplication window. Returns the new or existing item, and
+    /// whether an existing item is still in the queue.
+    pub async fn add_with_key(
+        &mut self,
+        event: String,
+        data: String,
+        key: String,
+    ) -> Result<Enqueued, StoreError> {
+        let mut job = QueueJob::new(event, data);
+        job.key = Some(key);
+
+        self.enqueue(job).await
+    }
+
+    /// Adds every recurring job that is due to the queue. This is safe to call from several
+    /// processes at once, as every run of a job is only queued once. Returns the IDs of the
+    /// items that were added.
+    ///
+    /// Nothing calls this on its own: jobs are only queued while some process calls it
+    /// periodically, e.g. once a minute, as cron schedules have a resolution of one minute.
+    pub async fn run_schedules(&mut self) -> Result<Vec<i32>, StoreError> {
+        let now = SystemTime::now();
+        let mut out = Vec::new();
+
+        for job in self.select_schedules(true).await? {
+            let next_run = job.schedule.next(job.next_run, now);
+            if let Some(id) = self.fire_schedule(&job.name, job.tick, next_run).await? {
+                out.push(id);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Takes the lease of an item claimed through `QueueStore::get_item` on this store, if its
+    /// visibility timeout has not passed yet.
+    fn take_lease(&mut self, id: i32) -> Result<i64, StoreError> {
+        match self.leases.remove(&id) {
+            Some((lease, deadline)) if deadline > Instant::now() => Ok(lease),
+            Some((lease, _)) => Err(LeaseLost { id, lease }.into()),
+            None => Err(format!("queue item {} was not claimed by this store", id).into()),
+        }
+    }
+}
 
 #[async_trait::async_trait]
 impl<'a> QueueStore for CellarEntityStore<'a> {
+    /// Claims an item. As `QueueItem` cannot hold the lease of the claim, it is kept by this
+    ///  store, and the item can only be marked as a success or failure through this same store,
+    ///  before its visibility timeout passes. Use `pop_queue` to handle the lease yourself.
     async fn get_item(&mut self) -> Result<Option<QueueItem>, StoreError> {
         let item = self.pop_queue().await?;
 
-        Ok(item.map(|(event, data)| QueueItem { id: 0, event, data }))
+        // leases of items that were never marked are of no use after their timeout
+        let now = Instant::now();
+        self.leases.retain(|_, (_, deadline)| *deadline > now);
+
+        let deadline = now + self.queue_options.visibility_timeout;
+        Ok(item.map(|item| {
+            self.leases.insert(item.id, (item.lease, deadline));
+
+            QueueItem {
+                id: item.id,
+                event: item.event,
+                data: item.data,
+            }
+        }))
     }
 
-    async fn mark_success(&mut self, _: QueueItem) -> Result<(), StoreError> {
-        Ok(())
+    async fn mark_success(&mut self, item: QueueItem) -> Result<(), StoreError> {
+        let lease = self.take_lease(item.id)?;
+        self.ack_queue(item.id, lease).await
     }
 
     async fn mark_failure(&mut self, item: QueueItem) -> Result<(), StoreError> {
-        let QueueItem { event, data, .. } = item;
-        self.push_queue(event, data).await
+        let lease = self.take_lease(item.id)?;
+        self.fail_queue(item.id, lease).await
     }
 
     async fn add(&mut self, event: String, data: String) -> Result<(), StoreError> {
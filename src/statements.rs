@@ -7,14 +7,46 @@ pub struct Statements<'a> {
     pub select_quad: Statement<'a>,
     pub insert_quads: Statement<'a>,
     pub delete_quads: Statement<'a>,
+    pub update_quads: Statement<'a>,
+    pub select_version: Statement<'a>,
     pub insert_collection: Statement<'a>,
     pub delete_collection: Statement<'a>,
     pub select_collection: Statement<'a>,
     pub select_collection_reverse: Statement<'a>,
     pub select_collection_inverse: Statement<'a>,
+    pub select_collection_inverse_forward: Statement<'a>,
     pub find_collection: Statement<'a>,
-    pub queue_item_pop: Statement<'a>,
+    pub queue_item_claim: Statement<'a>,
     pub queue_item_put: Statement<'a>,
+    pub queue_item_ack: Statement<'a>,
+    pub queue_item_fail: Statement<'a>,
+    pub queue_key_select: Statement<'a>,
+    pub queue_key_prune: Statement<'a>,
+    pub queue_stats: Statement<'a>,
+    pub queue_peek: Statement<'a>,
+    pub queue_requeue: Statement<'a>,
+    pub queue_purge: Statement<'a>,
+    pub queue_pause: Statement<'a>,
+    pub queue_resume: Statement<'a>,
+    pub queue_paused: Statement<'a>,
+    pub schedule_put: Statement<'a>,
+    pub schedule_delete: Statement<'a>,
+    pub schedule_select: Statement<'a>,
+    pub schedule_fire: Statement<'a>,
+    pub select_collection_count: Statement<'a>,
+    pub select_collection_inverse_count: Statement<'a>,
+    pub select_collection_range: Statement<'a>,
+    pub insert_collection_many: Statement<'a>,
+    pub delete_collection_many: Statement<'a>,
+    pub select_collection_intersect: Statement<'a>,
+    pub select_collection_intersect_reverse: Statement<'a>,
+    pub select_collection_union_count: Statement<'a>,
+    pub select_collection_members: Statement<'a>,
+    pub select_collection_merged: Statement<'a>,
+    pub select_collection_merged_reverse: Statement<'a>,
+    pub select_collection_ordered: Statement<'a>,
+    pub select_collection_ordered_reverse: Statement<'a>,
+    pub queue_item_claim_events: Statement<'a>,
 }
 
 const STATEMENTS: &[&'static str] = &[
@@ -28,34 +60,130 @@ const STATEMENTS: &[&'static str] = &[
     "select id, quad_id, subject_id, predicate_id, attribute_id, object, type_id, language from quad where quad_id = $1",
 
     // insert_quads
-    "insert into quad (quad_id, subject_id, predicate_id, attribute_id, object, type_id, language) select unnest($1::int[]), unnest($2::int[]), unnest($3::int[]), unnest($4::int[]), unnest($5::text[]), unnest($6::int[]), unnest($7::text[])",
+    "with inserted as (insert into quad (quad_id, subject_id, predicate_id, attribute_id, object, type_id, language) select unnest($1::int[]), unnest($2::int[]), unnest($3::int[]), unnest($4::int[]), unnest($5::text[]), unnest($6::int[]), unnest($7::text[])), version as (insert into entity_version (quad_id, version) select distinct quad_id, 1 from unnest($1::int[]) quad_id on conflict (quad_id) do update set version = entity_version.version + 1) select url, pg_notify('cellar_entity', url) from attribute where id = any($1::int[])",
 
     // delete_quads
-    "delete from quad where quad_id = $1",
+    "with deleted as (delete from quad where quad_id = $1), version as (insert into entity_version (quad_id, version) values ($1, 1) on conflict (quad_id) do update set version = entity_version.version + 1) select url, pg_notify('cellar_entity', url) from attribute where id = $1",
 
     // insert_collection
-    "insert into collection_item (collection_id, object_id) values ($1, $2) on conflict do nothing",
+    "with inserted as (insert into collection_item (collection_id, object_id) values ($1, $2) on conflict do nothing returning collection_id, object_id), counted as (insert into collection_count (collection_id, count) select collection_id, 1 from inserted on conflict (collection_id) do update set count = collection_count.count + 1) insert into collection_object_count (object_id, count) select object_id, 1 from inserted on conflict (object_id) do update set count = collection_object_count.count + 1",
 
     // delete_collection
-    "delete from collection_item where collection_id = $1 and object_id = $2",
+    "with deleted as (delete from collection_item where collection_id = $1 and object_id = $2 returning collection_id, object_id), counted as (update collection_count set count = count - 1 where collection_id = (select collection_id from deleted)) update collection_object_count set count = count - 1 where object_id = (select object_id from deleted)",
 
     // select_collection
-    "select id, collection_id, object_id from collection_item where collection_id = $1 and id >= $2 order by id asc limit $3",
+    "select id, collection_id, object_id, extract(epoch from added_at)::float8 from collection_item where collection_id = $1 and id >= $2 order by id asc limit $3",
 
     // select_collection_reverse
-    "select id, collection_id, object_id from collection_item where collection_id = $1 and id <= $2 order by id desc limit $3",
+    "select id, collection_id, object_id, extract(epoch from added_at)::float8 from collection_item where collection_id = $1 and id <= $2 order by id desc limit $3",
 
     // select_collection_inverse
-    "select id, collection_id, object_id from collection_item where object_id = $1",
+    "select ci.id, ci.collection_id, ci.object_id, extract(epoch from ci.added_at)::float8 from collection_item ci join attribute a on a.id = ci.collection_id where ci.object_id = $1 and ci.id <= $2 and ($4::text is null or left(a.url, length($4::text)) = $4::text) order by ci.id desc limit $3",
+
+    // select_collection_inverse_forward
+    "select ci.id, ci.collection_id, ci.object_id, extract(epoch from ci.added_at)::float8 from collection_item ci join attribute a on a.id = ci.collection_id where ci.object_id = $1 and ci.id >= $2 and ($4::text is null or left(a.url, length($4::text)) = $4::text) order by ci.id asc limit $3",
 
     // find_collection
-    "select id, collection_id, object_id from collection_item where collection_id = $1 and id = $2",
+    "select id, collection_id, object_id, extract(epoch from added_at)::float8 from collection_item where collection_id = $1 and object_id = $2",
 
-    // queue_item_pop
-    "delete from queue_item where id = (select id from queue_item order by id limit 1) returning event, data",
+    // queue_item_claim
+    "with reaped as (update queue_item set state = 'dead', locked_until = null, lease = null where state = 'pending' and locked_until < now() and attempts >= $2 returning group_key), candidate as (select q.id, q.locked_until is not null as reclaimed from (select q.id, q.priority, '-infinity'::timestamptz as turn from queue_item q where q.state = 'pending' and q.locked_until < now() and q.attempts < $2 and not exists (select 1 from queue_pause p where p.event = q.event) union all select c.id, c.priority, g.last_claimed_at from queue_group g cross join lateral (select q.id, q.priority from queue_item q where coalesce(q.group_key, '') = g.group_key and q.state = 'pending' and q.locked_until is null and q.run_after <= now() and q.attempts < $2 and not exists (select 1 from queue_pause p where p.event = q.event) order by q.priority desc, q.id limit 1) c where g.group_key = '' or $3::int is null or g.in_flight < $3::int) c join queue_item q on q.id = c.id where q.state = 'pending' and (q.locked_until is null or q.locked_until < now()) order by c.priority desc, c.turn, c.id limit 1 for update of q skip locked), claimed as (update queue_item set attempts = attempts + 1, lease = nextval('queue_lease_seq'), locked_until = now() + $1::float8 * interval '1 second' where id = (select id from candidate) returning id, event, data, attempts, group_key, lease), deltas as (select group_key, -1 as delta, '-infinity'::timestamptz as claimed_at from reaped where group_key is not null union all select group_key, case when (select reclaimed from candidate) then 0 else 1 end, now() from claimed where group_key is not null), counted as (insert into queue_group (group_key, last_claimed_at, in_flight) select group_key, max(claimed_at), sum(delta)::int from deltas group by group_key on conflict (group_key) do update set last_claimed_at = greatest(queue_group.last_claimed_at, excluded.last_claimed_at), in_flight = queue_group.in_flight + excluded.in_flight), touched as (update queue_group set last_claimed_at = now() where group_key in (select group_key from queue_group where group_key = '' and exists (select 1 from claimed where group_key is null) for update skip locked)) select id, event, data, attempts, group_key, lease from claimed",
 
     // queue_item_put
-    "insert into queue_item (event, data) values ($1, $2)"
+    "with new_id as (select nextval('queue_item_id_seq')::int as id), keyed as (insert into queue_key (key, item_id) select $6::text, id from new_id where $6::text is not null on conflict (key) do update set item_id = excluded.item_id, created_at = now() where queue_key.created_at < now() - $7::float8 * interval '1 second' returning item_id), item as (insert into queue_item (id, event, data, run_after, priority, group_key) select id, $1::text, $2::text, coalesce(to_timestamp($3::float8), now()), $4::int, nullif($5::text, '') from new_id where $6::text is null or exists (select 1 from keyed) returning id, event, group_key), grouped as (insert into queue_group (group_key, last_claimed_at) select group_key, '-infinity' from item where group_key is not null on conflict (group_key) do nothing) select id, true, true from item cross join lateral pg_notify('cellar_queue', item.event) union all select item_id, false, exists (select 1 from queue_item i where i.id = item_id) from queue_key where key = $6::text and not exists (select 1 from item)",
+
+    // update_quads
+    "with version as (insert into entity_version (quad_id, version) select $1::int, 1 where $2::bigint = 0 or exists (select 1 from entity_version where quad_id = $1::int) on conflict (quad_id) do update set version = entity_version.version + 1 where entity_version.version = $2::bigint returning version), deleted as (delete from quad where id = any($3::int[]) and exists (select 1 from version)), inserted as (insert into quad (quad_id, subject_id, predicate_id, attribute_id, object, type_id, language) select $1::int, unnest($4::int[]), unnest($5::int[]), unnest($6::int[]), unnest($7::text[]), unnest($8::int[]), unnest($9::text[]) where exists (select 1 from version)) select version, pg_notify('cellar_entity', (select url from attribute where id = $1::int)) from version",
+
+    // select_version
+    "select version from entity_version where quad_id = $1",
+
+    // queue_item_ack
+    "with acked as (delete from queue_item where id = $1 and lease = $2 returning id, group_key), counted as (update queue_group set in_flight = in_flight - 1 where group_key = (select group_key from acked)), pruned as (delete from queue_key where key in (select key from queue_key where created_at < now() - $3::float8 * interval '1 second' limit 100 for update skip locked)) select id from acked",
+
+    // queue_item_fail
+    "with failed as (update queue_item set state = case when attempts >= $2 then 'dead' else 'pending' end, locked_until = null, lease = null, run_after = now() + least($3 * power(2, attempts - 1), $4) * (0.5 + random() / 2) * interval '1 second' where id = $1 and lease = $5 returning id, group_key), counted as (update queue_group set in_flight = in_flight - 1 where group_key = (select group_key from failed)) select id from failed",
+
+    // queue_key_select
+    "select item_id, exists (select 1 from queue_item i where i.id = item_id) from queue_key where key = $1",
+
+    // queue_key_prune
+    "delete from queue_key where created_at < now() - $1::float8 * interval '1 second'",
+
+    // queue_stats
+    "select event, case when state = 'dead' then 'dead' when locked_until >= now() then 'running' when run_after > now() then 'scheduled' else 'pending' end as status, count(*), extract(epoch from now() - min(created_at))::float8 from queue_item group by 1, 2 order by 1, 2",
+
+    // queue_peek
+    "select id, event, data, attempts, group_key, priority, case when state = 'dead' then 'dead' when locked_until >= now() then 'running' when run_after > now() then 'scheduled' else 'pending' end as status, extract(epoch from created_at)::float8, extract(epoch from run_after)::float8 from queue_item where ($1::text is null or event = $1::text) and ($2::text is null or case when state = 'dead' then 'dead' when locked_until >= now() then 'running' when run_after > now() then 'scheduled' else 'pending' end = $2::text) order by id limit $3",
+
+    // queue_requeue
+    "with requeued as (update queue_item set state = 'pending', attempts = 0, run_after = now(), locked_until = null where state = 'dead' and ($1::text is null or event = $1::text) returning id, event) select id from requeued cross join lateral pg_notify('cellar_queue', requeued.event)",
+
+    // queue_purge
+    "with purged as (delete from queue_item where ($1::text is null or event = $1::text) and ($2::text is null or group_key = $2::text) returning id, group_key, locked_until), counted as (update queue_group g set in_flight = g.in_flight - p.count from (select group_key, count(*)::int as count from purged where group_key is not null and locked_until is not null group by group_key) p where g.group_key = p.group_key) select id from purged",
+
+    // queue_pause
+    "insert into queue_pause (event) values ($1) on conflict do nothing",
+
+    // queue_resume
+    "delete from queue_pause where event = $1",
+
+    // queue_paused
+    "select event from queue_pause order by event",
+
+    // schedule_put
+    "insert into queue_schedule (name, event, data, interval_secs, cron, next_run) values ($1, $2, $3, $4, $5, to_timestamp($6::float8)) on conflict (name) do update set event = excluded.event, data = excluded.data, interval_secs = excluded.interval_secs, cron = excluded.cron, next_run = excluded.next_run",
+
+    // schedule_delete
+    "delete from queue_schedule where name = $1",
+
+    // schedule_select
+    "select name, event, data, interval_secs, cron, extract(epoch from next_run)::float8, tick from queue_schedule where not $1::bool or next_run <= now() order by name",
+
+    // schedule_fire
+    "with advanced as (update queue_schedule set next_run = to_timestamp($3::float8), tick = tick + 1 where name = $1 and tick = $2 returning event, data), item as (insert into queue_item (event, data) select event, data from advanced returning id, event) select id from item cross join lateral pg_notify('cellar_queue', item.event)",
+
+    // select_collection_count
+    "select count from collection_count where collection_id = $1",
+
+    // select_collection_inverse_count
+    "select case when $2::text is null then coalesce((select count from collection_object_count where object_id = $1), 0) else (select count(*) from collection_item ci join attribute a on a.id = ci.collection_id where ci.object_id = $1 and left(a.url, length($2::text)) = $2::text) end",
+
+    // select_collection_range
+    "select id, collection_id, object_id, extract(epoch from added_at)::float8, (extract(epoch from added_at) * 1000000)::int8 from collection_item where collection_id = $1 and ($2::float8 is null or added_at >= to_timestamp($2::float8)) and ($3::float8 is null or added_at < to_timestamp($3::float8)) and ($5::int8 is null or (added_at, id) < (to_timestamp(0) + $5::int8 * interval '1 microsecond', $6::int4)) order by added_at desc, id desc limit $4",
+
+    // insert_collection_many
+    "with inserted as (insert into collection_item (collection_id, object_id) select unnest($1::int[]), unnest($2::int[]) on conflict do nothing returning collection_id, object_id), counted as (insert into collection_count (collection_id, count) select collection_id, count(*) from inserted group by collection_id on conflict (collection_id) do update set count = collection_count.count + excluded.count), object_counted as (insert into collection_object_count (object_id, count) select object_id, count(*) from inserted group by object_id on conflict (object_id) do update set count = collection_object_count.count + excluded.count) select collection_id, object_id from inserted",
+
+    // delete_collection_many
+    "with deleted as (delete from collection_item c using unnest($1::int[], $2::int[]) as d (collection_id, object_id) where c.collection_id = d.collection_id and c.object_id = d.object_id returning c.collection_id, c.object_id), counted as (update collection_count set count = collection_count.count - d.count from (select collection_id, count(*) as count from deleted group by collection_id) d where collection_count.collection_id = d.collection_id), object_counted as (update collection_object_count set count = collection_object_count.count - d.count from (select object_id, count(*) as count from deleted group by object_id) d where collection_object_count.object_id = d.object_id) select collection_id, object_id from deleted",
+
+    // select_collection_intersect
+    "select a.id, a.collection_id, a.object_id, extract(epoch from a.added_at)::float8 from collection_item a where a.collection_id = $1 and a.id >= $3 and exists (select 1 from collection_item b where b.collection_id = $2 and b.object_id = a.object_id) <> $5::bool order by a.id asc limit $4",
+
+    // select_collection_intersect_reverse
+    "select a.id, a.collection_id, a.object_id, extract(epoch from a.added_at)::float8 from collection_item a where a.collection_id = $1 and a.id <= $3 and exists (select 1 from collection_item b where b.collection_id = $2 and b.object_id = a.object_id) <> $5::bool order by a.id desc limit $4",
+
+    // select_collection_union_count
+    "select count(distinct object_id) from collection_item where collection_id = any($1::int[])",
+
+    // select_collection_members
+    "select object_id from collection_item where collection_id = $1 and object_id = any($2::int[])",
+
+    // select_collection_merged
+    "select id, collection_id, object_id, extract(epoch from added_at)::float8 from collection_item where collection_id = any($1::int[]) and id >= $2 order by id asc limit $3",
+
+    // select_collection_merged_reverse
+    "select id, collection_id, object_id, extract(epoch from added_at)::float8 from collection_item where collection_id = any($1::int[]) and id <= $2 order by id desc limit $3",
+
+    // select_collection_ordered
+    "select ci.id, ci.collection_id, ci.object_id, extract(epoch from ci.added_at)::float8, coalesce(v.value, ''), coalesce(v.class, 2), coalesce(v.number, 0)::text from collection_item ci left join lateral (select case when k.number is null then 2 else k.class end as class, coalesce(k.number, 0) as number, k.value from (select q.object as value, case when t.url = any($6::text[]) and q.object ~ $8::text then 0 when t.url = any($7::text[]) and q.object ~ $9::text then 1 end as class, case when t.url = any($6::text[]) and q.object ~ $8::text then try_numeric(q.object) when t.url = any($7::text[]) and q.object ~ $9::text then extract(epoch from try_timestamptz(q.object))::numeric end as number from quad q left join attribute t on t.id = q.type_id where q.quad_id = ci.object_id and q.subject_id = ci.object_id and q.predicate_id = $2) k order by 1, 2, 3 limit 1) v on true where ci.collection_id = $1 and ($3::text is null or (coalesce(v.class, 2), coalesce(v.number, 0), coalesce(v.value, ''), ci.id) > ($10::int, $11::text::numeric, $3::text, $4::int)) order by coalesce(v.class, 2) asc, coalesce(v.number, 0) asc, coalesce(v.value, '') asc, ci.id asc limit $5",
+
+    // select_collection_ordered_reverse
+    "select ci.id, ci.collection_id, ci.object_id, extract(epoch from ci.added_at)::float8, coalesce(v.value, ''), coalesce(v.class, 2), coalesce(v.number, 0)::text from collection_item ci left join lateral (select case when k.number is null then 2 else k.class end as class, coalesce(k.number, 0) as number, k.value from (select q.object as value, case when t.url = any($6::text[]) and q.object ~ $8::text then 0 when t.url = any($7::text[]) and q.object ~ $9::text then 1 end as class, case when t.url = any($6::text[]) and q.object ~ $8::text then try_numeric(q.object) when t.url = any($7::text[]) and q.object ~ $9::text then extract(epoch from try_timestamptz(q.object))::numeric end as number from quad q left join attribute t on t.id = q.type_id where q.quad_id = ci.object_id and q.subject_id = ci.object_id and q.predicate_id = $2) k order by 1, 2, 3 limit 1) v on true where ci.collection_id = $1 and ($3::text is null or (coalesce(v.class, 2), coalesce(v.number, 0), coalesce(v.value, ''), ci.id) < ($10::int, $11::text::numeric, $3::text, $4::int)) order by coalesce(v.class, 2) desc, coalesce(v.number, 0) desc, coalesce(v.value, '') desc, ci.id desc limit $5",
+
+    // queue_item_claim_events
+    "with reaped as (update queue_item set state = 'dead', locked_until = null, lease = null where state = 'pending' and locked_until < now() and attempts >= $2 returning group_key), candidate as (select q.id, q.locked_until is not null as reclaimed from (select q.id, q.priority, '-infinity'::timestamptz as turn from queue_item q where q.state = 'pending' and q.locked_until < now() and q.attempts < $2 and q.event = any($3::text[]) and not exists (select 1 from queue_pause p where p.event = q.event) union all select c.id, c.priority, g.last_claimed_at from queue_group g cross join lateral (select i.id, i.priority from unnest($3::text[]) e (event) cross join lateral (select q.id, q.priority from queue_item q where coalesce(q.group_key, '') = g.group_key and q.event = e.event and q.state = 'pending' and q.locked_until is null and q.run_after <= now() and q.attempts < $2 order by q.priority desc, q.id limit 1) i where not exists (select 1 from queue_pause p where p.event = e.event) order by i.priority desc, i.id limit 1) c where g.group_key = '' or $4::int is null or g.in_flight < $4::int) c join queue_item q on q.id = c.id where q.state = 'pending' and (q.locked_until is null or q.locked_until < now()) order by c.priority desc, c.turn, c.id limit 1 for update of q skip locked), claimed as (update queue_item set attempts = attempts + 1, lease = nextval('queue_lease_seq'), locked_until = now() + $1::float8 * interval '1 second' where id = (select id from candidate) returning id, event, data, attempts, group_key, lease), deltas as (select group_key, -1 as delta, '-infinity'::timestamptz as claimed_at from reaped where group_key is not null union all select group_key, case when (select reclaimed from candidate) then 0 else 1 end, now() from claimed where group_key is not null), counted as (insert into queue_group (group_key, last_claimed_at, in_flight) select group_key, max(claimed_at), sum(delta)::int from deltas group by group_key on conflict (group_key) do update set last_claimed_at = greatest(queue_group.last_claimed_at, excluded.last_claimed_at), in_flight = queue_group.in_flight + excluded.in_flight), touched as (update queue_group set last_claimed_at = now() where group_key in (select group_key from queue_group where group_key = '' and exists (select 1 from claimed where group_key is null) for update skip locked)) select id, event, data, attempts, group_key, lease from claimed"
 ];
 
 impl<'a> Statements<'a> {
@@ -71,9 +199,41 @@ impl<'a> Statements<'a> {
             select_collection: Statement::parse(frontend, STATEMENTS[7]).await?,
             select_collection_reverse: Statement::parse(frontend, STATEMENTS[8]).await?,
             select_collection_inverse: Statement::parse(frontend, STATEMENTS[9]).await?,
-            find_collection: Statement::parse(frontend, STATEMENTS[10]).await?,
-            queue_item_pop: Statement::parse(frontend, STATEMENTS[11]).await?,
-            queue_item_put: Statement::parse(frontend, STATEMENTS[12]).await?,
+            select_collection_inverse_forward: Statement::parse(frontend, STATEMENTS[10]).await?,
+            find_collection: Statement::parse(frontend, STATEMENTS[11]).await?,
+            queue_item_claim: Statement::parse(frontend, STATEMENTS[12]).await?,
+            queue_item_put: Statement::parse(frontend, STATEMENTS[13]).await?,
+            update_quads: Statement::parse(frontend, STATEMENTS[14]).await?,
+            select_version: Statement::parse(frontend, STATEMENTS[15]).await?,
+            queue_item_ack: Statement::parse(frontend, STATEMENTS[16]).await?,
+            queue_item_fail: Statement::parse(frontend, STATEMENTS[17]).await?,
+            queue_key_select: Statement::parse(frontend, STATEMENTS[18]).await?,
+            queue_key_prune: Statement::parse(frontend, STATEMENTS[19]).await?,
+            queue_stats: Statement::parse(frontend, STATEMENTS[20]).await?,
+            queue_peek: Statement::parse(frontend, STATEMENTS[21]).await?,
+            queue_requeue: Statement::parse(frontend, STATEMENTS[22]).await?,
+            queue_purge: Statement::parse(frontend, STATEMENTS[23]).await?,
+            queue_pause: Statement::parse(frontend, STATEMENTS[24]).await?,
+            queue_resume: Statement::parse(frontend, STATEMENTS[25]).await?,
+            queue_paused: Statement::parse(frontend, STATEMENTS[26]).await?,
+            schedule_put: Statement::parse(frontend, STATEMENTS[27]).await?,
+            schedule_delete: Statement::parse(frontend, STATEMENTS[28]).await?,
+            schedule_select: Statement::parse(frontend, STATEMENTS[29]).await?,
+            schedule_fire: Statement::parse(frontend, STATEMENTS[30]).await?,
+            select_collection_count: Statement::parse(frontend, STATEMENTS[31]).await?,
+            select_collection_inverse_count: Statement::parse(frontend, STATEMENTS[32]).await?,
+            select_collection_range: Statement::parse(frontend, STATEMENTS[33]).await?,
+            insert_collection_many: Statement::parse(frontend, STATEMENTS[34]).await?,
+            delete_collection_many: Statement::parse(frontend, STATEMENTS[35]).await?,
+            select_collection_intersect: Statement::parse(frontend, STATEMENTS[36]).await?,
+            select_collection_intersect_reverse: Statement::parse(frontend, STATEMENTS[37]).await?,
+            select_collection_union_count: Statement::parse(frontend, STATEMENTS[38]).await?,
+            select_collection_members: Statement::parse(frontend, STATEMENTS[39]).await?,
+            select_collection_merged: Statement::parse(frontend, STATEMENTS[40]).await?,
+            select_collection_merged_reverse: Statement::parse(frontend, STATEMENTS[41]).await?,
+            select_collection_ordered: Statement::parse(frontend, STATEMENTS[42]).await?,
+            select_collection_ordered_reverse: Statement::parse(frontend, STATEMENTS[43]).await?,
+            queue_item_claim_events: Statement::parse(frontend, STATEMENTS[44]).await?,
         })
     }
 }
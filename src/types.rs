@@ -1,9 +1,84 @@
-use postgres_async::types::Row;
+use crate::cron::{CronSchedule, Schedule};
+use postgres_async::types::{AnyError, Row};
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Converts a time to seconds since the UNIX epoch, as taken by `to_timestamp`.
+pub(crate) fn time_to_epoch(time: SystemTime) -> f64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+/// Converts seconds since the UNIX epoch, as returned by `extract(epoch from ...)`, to a time.
+pub(crate) fn epoch_to_time(epoch: f64) -> SystemTime {
+    if epoch >= 0.0 {
+        UNIX_EPOCH + Duration::from_secs_f64(epoch)
+    } else {
+        UNIX_EPOCH - Duration::from_secs_f64(-epoch)
+    }
+}
+
+/// The XSD datatypes whose literals are compared as numbers.
+pub(crate) const NUMERIC_TYPES: &[&str] = &[
+    "http://www.w3.org/2001/XMLSchema#decimal",
+    "http://www.w3.org/2001/XMLSchema#integer",
+    "http://www.w3.org/2001/XMLSchema#float",
+    "http://www.w3.org/2001/XMLSchema#double",
+    "http://www.w3.org/2001/XMLSchema#long",
+    "http://www.w3.org/2001/XMLSchema#int",
+    "http://www.w3.org/2001/XMLSchema#short",
+    "http://www.w3.org/2001/XMLSchema#byte",
+    "http://www.w3.org/2001/XMLSchema#nonNegativeInteger",
+    "http://www.w3.org/2001/XMLSchema#positiveInteger",
+    "http://www.w3.org/2001/XMLSchema#nonPositiveInteger",
+    "http://www.w3.org/2001/XMLSchema#negativeInteger",
+    "http://www.w3.org/2001/XMLSchema#unsignedLong",
+    "http://www.w3.org/2001/XMLSchema#unsignedInt",
+    "http://www.w3.org/2001/XMLSchema#unsignedShort",
+    "http://www.w3.org/2001/XMLSchema#unsignedByte",
+];
+
+/// The XSD datatypes whose literals are compared as points in time.
+pub(crate) const DATETIME_TYPES: &[&str] = &[
+    "http://www.w3.org/2001/XMLSchema#dateTime",
+    "http://www.w3.org/2001/XMLSchema#dateTimeStamp",
+];
+
+/// The values of numeric literals that can be cast to `numeric`. Others are compared as strings.
+pub(crate) const NUMBER_PATTERN: &str = "^[+-]?([0-9]+([.][0-9]*)?|[.][0-9]+)([eE][+-]?[0-9]+)?$";
+
+/// The values of date-time literals that can be cast to `timestamptz`. Others are compared as
+/// strings.
+pub(crate) const DATETIME_PATTERN: &str =
+    "^-?[0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}:[0-9]{2}([.][0-9]+)?(Z|[+-][0-9]{2}:[0-9]{2})?$";
+
+/// The key a collection item is ordered by in `select_collection_ordered`. Numbers sort
+/// before points in time, which sort before any other value.
+#[derive(Debug, Clone)]
+pub struct OrderedKey {
+    /// 0 for numbers, 1 for points in time, and 2 for any other value.
+    pub class: i32,
+
+    /// The number, or the seconds since the UNIX epoch for points in time, as a decimal.
+    pub number: String,
+
+    /// The value as it is stored.
+    pub value: String,
+
+    /// The row of the item.
+    pub id: i32,
+}
 
 pub struct CollectionItem {
     pub id: i32,
     pub collection_id: i32,
     pub object_id: i32,
+
+    /// When the object was inserted into the collection.
+    pub added_at: SystemTime,
 }
 
 impl CollectionItem {
@@ -12,6 +87,217 @@ impl CollectionItem {
             id: row.get(0).unwrap().unwrap(),
             collection_id: row.get(1).unwrap().unwrap(),
             object_id: row.get(2).unwrap().unwrap(),
+            added_at: epoch_to_time(row.get(3).unwrap().unwrap()),
+        }
+    }
+}
+
+/// A new item to be added to the queue.
+#[derive(Debug, Clone)]
+pub struct QueueJob {
+    pub event: String,
+    pub data: String,
+
+    /// The item will not be claimed before this time. If `None`, it can be claimed immediately.
+    pub run_after: Option<SystemTime>,
+
+    /// Items with a higher priority are claimed before items with a lower one. Defaults to 0.
+    pub priority: i32,
+
+    /// An optional grouping key, such as the destination host. Groups are claimed round-robin,
+    ///  and can be limited to an amount of items in flight at once. An empty key is the same
+    ///  as no key.
+    pub group: Option<String>,
+
+    /// An optional idempotency key. Adding an item with the same key as one that was added within
+    ///  the deduplication window does nothing, and returns the existing item instead.
+    pub key: Option<String>,
+}
+
+impl QueueJob {
+    pub fn new(event: String, data: String) -> QueueJob {
+        QueueJob {
+            event,
+            data,
+            run_after: None,
+            priority: 0,
+            group: None,
+            key: None,
+        }
+    }
+
+    /// The `run_after` time as seconds since the UNIX epoch, as the database takes it.
+    pub(crate) fn run_after_epoch(&self) -> Option<f64> {
+        self.run_after
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|time| time.as_secs_f64())
+    }
+}
+
+/// The outcome of adding an item to the queue.
+#[derive(Debug, Clone, Copy)]
+pub struct Enqueued {
+    pub id: i32,
+
+    /// Whether a new item was added. If not, an item with the same idempotency key was added
+    ///  within the deduplication window.
+    pub added: bool,
+
+    /// Whether the item is still in the queue. An existing item found by its idempotency key may
+    ///  have been handled and removed already.
+    pub exists: bool,
+}
+
+/// A single item in the queue, as claimed by a worker.
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+    pub id: i32,
+    pub event: String,
+    pub data: String,
+
+    /// The amount of times this item has been claimed, including this time.
+    pub attempts: i32,
+
+    pub group: Option<String>,
+
+    /// Identifies this claim of the item. It has to be passed when acknowledging or failing
+    /// the item, so a worker whose claim timed out cannot touch it after it was claimed again.
+    pub lease: i64,
+}
+
+impl QueueEntry {
+    pub fn make_from_row(row: &Row) -> QueueEntry {
+        QueueEntry {
+            id: row.get(0).unwrap().unwrap(),
+            event: row.get(1).unwrap().unwrap(),
+            data: row.get(2).unwrap().unwrap(),
+            attempts: row.get(3).unwrap().unwrap(),
+            group: row.get(4).unwrap(),
+            lease: row.get(5).unwrap().unwrap(),
+        }
+    }
+}
+
+/// The amount of queue items with a specific event and state.
+#[derive(Debug, Clone)]
+pub struct QueueStats {
+    pub event: String,
+
+    /// One of `pending`, `scheduled`, `running` or `dead`.
+    pub state: String,
+    pub count: i64,
+
+    /// The time since the oldest of these items was added.
+    pub oldest: Duration,
+}
+
+impl QueueStats {
+    pub fn make_from_row(row: &Row) -> QueueStats {
+        QueueStats {
+            event: row.get(0).unwrap().unwrap(),
+            state: row.get(1).unwrap().unwrap(),
+            count: row.get(2).unwrap().unwrap(),
+            oldest: Duration::from_secs_f64(row.get::<f64>(3).unwrap().unwrap().max(0.0)),
+        }
+    }
+}
+
+/// A full description of a single item in the queue, as shown to operators.
+#[derive(Debug, Clone)]
+pub struct QueueItemInfo {
+    pub id: i32,
+    pub event: String,
+    pub data: String,
+    pub attempts: i32,
+    pub group: Option<String>,
+    pub priority: i32,
+
+    /// One of `pending`, `scheduled`, `running` or `dead`.
+    pub state: String,
+    pub created_at: SystemTime,
+    pub run_after: SystemTime,
+}
+
+impl QueueItemInfo {
+    pub fn make_from_row(row: &Row) -> QueueItemInfo {
+        QueueItemInfo {
+            id: row.get(0).unwrap().unwrap(),
+            event: row.get(1).unwrap().unwrap(),
+            data: row.get(2).unwrap().unwrap(),
+            attempts: row.get(3).unwrap().unwrap(),
+            group: row.get(4).unwrap(),
+            priority: row.get(5).unwrap().unwrap(),
+            state: row.get(6).unwrap().unwrap(),
+            created_at: epoch_to_time(row.get(7).unwrap().unwrap()),
+            run_after: epoch_to_time(row.get(8).unwrap().unwrap()),
         }
     }
 }
+
+/// A job that is added to the queue on a schedule.
+#[derive(Debug, Clone)]
+pub struct RecurringJob {
+    pub name: String,
+    pub event: String,
+    pub data: String,
+    pub schedule: Schedule,
+    pub next_run: SystemTime,
+
+    /// Incremented every time the job is added to the queue.
+    pub tick: i64,
+}
+
+impl RecurringJob {
+    pub fn make_from_row(row: &Row) -> Result<RecurringJob, AnyError> {
+        let schedule = match (row.get::<f64>(3)?, row.get::<String>(4)?) {
+            (Some(interval), _) => Schedule::Interval(Duration::from_secs_f64(interval)),
+            (_, Some(cron)) => Schedule::Cron(CronSchedule::parse(&cron)?),
+            _ => return Err("recurring job has no schedule".into()),
+        };
+
+        Ok(RecurringJob {
+            name: row.get(0)?.unwrap(),
+            event: row.get(1)?.unwrap(),
+            data: row.get(2)?.unwrap(),
+            schedule,
+            next_run: epoch_to_time(row.get(5)?.unwrap()),
+            tick: row.get(6)?.unwrap(),
+        })
+    }
+}
+
+/// Returned when a conditional put finds that the stored entity has moved on to another version.
+#[derive(Debug)]
+pub struct VersionConflict {
+    pub path: String,
+    pub expected: i64,
+    pub actual: i64,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "version conflict on {}: expected {}, found {}",
+            self.path, self.expected, self.actual
+        )
+    }
+}
+
+impl Error for VersionConflict {}
+
+/// Returned when acknowledging or failing a queue item whose claim timed out, and which may
+/// have been claimed by another worker since.
+#[derive(Debug)]
+pub struct LeaseLost {
+    pub id: i32,
+    pub lease: i64,
+}
+
+impl fmt::Display for LeaseLost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lease {} on queue item {} was lost", self.lease, self.id)
+    }
+}
+
+impl Error for LeaseLost {}
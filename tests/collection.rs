@@ -0,0 +1,167 @@
+//! These tests need a database with `schema/db.sql` loaded. They are skipped unless
+//! `CELLAR_TEST_ADDRESS`, `CELLAR_TEST_USER`, `CELLAR_TEST_PASSWORD` and `CELLAR_TEST_DATABASE`
+//! are set, either in the environment or in a `.env` file.
+
+use kroeg_cellar::{CellarConnection, CellarEntityStore};
+use kroeg_tap::{EntityStore, StoreItem};
+use serde_json::json;
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+async fn connect() -> Option<CellarConnection> {
+    dotenv::dotenv().ok();
+
+    let address = env::var("CELLAR_TEST_ADDRESS").ok()?;
+    let user = env::var("CELLAR_TEST_USER").ok()?;
+    let password = env::var("CELLAR_TEST_PASSWORD").ok()?;
+    let database = env::var("CELLAR_TEST_DATABASE").ok()?;
+
+    Some(
+        CellarConnection::connect(&address, &user, &password, &database)
+            .await
+            .unwrap(),
+    )
+}
+
+/// Makes an URI that is unique to this test run.
+fn unique(name: &str) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+    format!("https://cellar.test/{}/{}", now.as_nanos(), name)
+}
+
+/// Fills a collection with five items. The attributes of the items are created in reverse, so
+/// their attribute IDs are ordered differently from their position in the collection.
+async fn fill_collection(store: &mut CellarEntityStore<'_>) -> (String, Vec<String>) {
+    let collection = unique("collection");
+    let items: Vec<_> = (0..5).map(|i| unique(&format!("item-{}", i))).collect();
+
+    for item in items.iter().rev() {
+        store.cache_uris(&[item.to_owned()]).await.unwrap();
+    }
+
+    for item in &items {
+        EntityStore::insert_collection(store, collection.to_owned(), item.to_owned())
+            .await
+            .unwrap();
+    }
+
+    (collection, items)
+}
+
+#[test]
+fn find_collection_cursors_point_at_neighbours() {
+    async_std::task::block_on(async {
+        let conn = match connect().await {
+            Some(conn) => conn,
+            None => {
+                eprintln!("skipped, no test database configured");
+                return;
+            }
+        };
+
+        let mut store = CellarEntityStore::new(&conn);
+        let (collection, items) = fill_collection(&mut store).await;
+
+        let found = store
+            .find_collection(collection.to_owned(), items[2].to_owned())
+            .await
+            .unwrap();
+        assert_eq!(found.items, vec![items[2].to_owned()]);
+
+        let older = store
+            .read_collection(collection.to_owned(), Some(1), found.after)
+            .await
+            .unwrap();
+        assert_eq!(older.items, vec![items[1].to_owned()]);
+
+        let newer = store
+            .read_collection(collection.to_owned(), Some(1), found.before)
+            .await
+            .unwrap();
+        assert_eq!(newer.items, vec![items[3].to_owned()]);
+    });
+}
+
+#[test]
+fn find_collection_without_item_has_no_cursors() {
+    async_std::task::block_on(async {
+        let conn = match connect().await {
+            Some(conn) => conn,
+            None => {
+                eprintln!("skipped, no test database configured");
+                return;
+            }
+        };
+
+        let mut store = CellarEntityStore::new(&conn);
+        let (collection, _) = fill_collection(&mut store).await;
+
+        let found = store
+            .find_collection(collection, unique("missing"))
+            .await
+            .unwrap();
+        assert!(found.items.is_empty());
+        assert!(found.after.is_none());
+        assert!(found.before.is_none());
+    });
+}
+
+#[test]
+fn read_collection_ordered_compares_out_of_range_values_as_strings() {
+    async_std::task::block_on(async {
+        let conn = match connect().await {
+            Some(conn) => conn,
+            None => {
+                eprintln!("skipped, no test database configured");
+                return;
+            }
+        };
+
+        let mut store = CellarEntityStore::new(&conn);
+        let collection = unique("collection");
+        let predicate = unique("value");
+
+        // the second and third value match the number and date-time patterns, but cannot be cast
+        let values = [
+            ("5", "integer"),
+            ("1e1000000", "double"),
+            ("2021-02-30T00:00:00Z", "dateTime"),
+            ("2021-02-03T00:00:00Z", "dateTime"),
+        ];
+
+        let mut items = Vec::new();
+        for (i, (value, datatype)) in values.iter().enumerate() {
+            let item = unique(&format!("item-{}", i));
+            let datatype = format!("http://www.w3.org/2001/XMLSchema#{}", datatype);
+            let json = json!([{
+                "@id": item.to_owned(),
+                predicate.to_owned(): [{ "@value": value, "@type": datatype }],
+            }]);
+
+            let mut entity = StoreItem::parse(&item, &json).unwrap();
+            store.put(item.to_owned(), &mut entity).await.unwrap();
+            EntityStore::insert_collection(&mut store, collection.to_owned(), item.to_owned())
+                .await
+                .unwrap();
+
+            items.push(item);
+        }
+
+        let page = store
+            .read_collection_ordered(collection, predicate, None, None)
+            .await
+            .unwrap();
+
+        // numbers sort before points in time, which sort before strings, read from the end
+        assert_eq!(
+            page.items,
+            vec![
+                items[2].to_owned(),
+                items[1].to_owned(),
+                items[3].to_owned(),
+                items[0].to_owned(),
+            ]
+        );
+    });
+}